@@ -1,4 +1,38 @@
+use std::env;
+
 fn main() {
     #[cfg(not(target_os = "linux"))]
     compile_error!("This crate only support linux !");
+
+    // SO_TIMESTAMPNS and SO_TIMESTAMPING are not exported by libc and their
+    // values depend on the architecture, see sys::socket. The options are
+    // left out on the architectures whose values are not known.
+    println!("cargo:rustc-check-cfg=cfg(so_timestamping)");
+
+    let arch = env::var("CARGO_CFG_TARGET_ARCH").unwrap_or_default();
+
+    let known = matches!(
+        arch.as_str(),
+        "x86"
+            | "x86_64"
+            | "arm"
+            | "aarch64"
+            | "riscv32"
+            | "riscv64"
+            | "loongarch64"
+            | "powerpc"
+            | "powerpc64"
+            | "s390x"
+            | "mips"
+            | "mips64"
+            | "m68k"
+            | "csky"
+            | "hexagon"
+            | "sparc"
+            | "sparc64"
+    );
+
+    if known {
+        println!("cargo:rustc-cfg=so_timestamping");
+    }
 }
@@ -3,16 +3,11 @@ use std::time::Duration;
 use queen_io::sys::timerfd::*;
 
 fn main() {
-    let clock = Clock::Realtime;
+    let clock = Clock::Monotonic;
     let flags = TFD_CLOEXEC;
     let timerfd = TimerFd::create(clock, flags).unwrap();
 
-    let timerspec = TimerSpec {
-        interval: Duration::new(1, 0),
-        value: Duration::new(10, 0)
-    };
-
-    timerfd.settime(timerspec.clone(), SetTimeFlags::Default).unwrap();
+    timerfd.set_interval(Duration::new(1, 0)).unwrap();
 
     loop {
         println!("{:?}", timerfd.read());
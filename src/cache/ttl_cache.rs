@@ -418,7 +418,7 @@ impl<K: Eq + Hash, V, S: BuildHasher> TtlCache<K, V, S> {
         self.map.clear();
     }
 
-    pub fn entry(&mut self, k: K) -> Entry<K, V> {
+    pub fn entry(&mut self, k: K) -> Entry<'_, K, V> {
         let should_remove = self
             .map
             .get(&k)
@@ -451,7 +451,7 @@ impl<K: Eq + Hash, V, S: BuildHasher> TtlCache<K, V, S> {
     /// let kvs: Vec<_> = cache.iter().collect();
     /// assert_eq!(kvs, [(&2, &20), (&3, &30)]);
     /// ```
-    pub fn iter(&mut self) -> Iter<K, V> {
+    pub fn iter(&mut self) -> Iter<'_, K, V> {
         self.remove_expired();
         Iter(self.map.iter())
     }
@@ -486,7 +486,7 @@ impl<K: Eq + Hash, V, S: BuildHasher> TtlCache<K, V, S> {
     /// assert_eq!(cache.get(&2), Some(&200));
     /// assert_eq!(cache.get(&3), Some(&300));
     /// ```
-    pub fn iter_mut(&mut self) -> IterMut<K, V> {
+    pub fn iter_mut(&mut self) -> IterMut<'_, K, V> {
         self.remove_expired();
         IterMut(self.map.iter_mut())
     }
@@ -35,7 +35,7 @@ impl Events {
         self.inner.is_empty()
     }
 
-    pub fn iter(&self) -> Iter {
+    pub fn iter(&self) -> Iter<'_> {
         Iter {
             inner: self,
             pos: 0
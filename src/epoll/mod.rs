@@ -91,8 +91,7 @@ impl fmt::Debug for Epoll {
 
 fn validate_args(_token: Token, interest: Ready) -> io::Result<()> {
     if !interest.is_readable() && !interest.is_writable() {
-        return Err(io::Error::new(
-            io::ErrorKind::Other,
+        return Err(io::Error::other(
             "interest must include readable or writable",
         ));
     }
@@ -119,7 +118,7 @@ impl SelectorId {
         let selector_id = self.id.load(Ordering::SeqCst);
 
         if selector_id != 0 && selector_id != poll.0.id() {
-            Err(io::Error::new(io::ErrorKind::Other, "socket already added"))
+            Err(io::Error::other("socket already added"))
         } else {
             self.id.store(poll.0.id(), Ordering::SeqCst);
             Ok(())
@@ -1,3 +1,4 @@
+#[macro_use]
 pub mod sys;
 pub mod epoll;
 pub mod poll;
@@ -5,6 +6,8 @@ pub mod net;
 pub mod waker;
 pub mod cache;
 pub mod queue;
+pub mod process;
+pub mod timer;
 
 pub mod slab {
     pub use slab::*;
@@ -0,0 +1,353 @@
+use std::cell::Cell;
+use std::fs::File;
+use std::io;
+use std::net::SocketAddr;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::os::unix::net;
+use std::time::Duration;
+
+use crate::epoll::{Epoll, EpollOpt, Ready, Source, Token};
+use crate::sys::timerfd::TimerFd;
+
+use super::tcp::{TcpListener, TcpStream};
+use super::unix::{UnixListener, UnixStream};
+
+/// A listening socket that `Acceptor` can accept connections from.
+pub trait Accept: Source + AsRawFd {
+    type Stream;
+    type Addr;
+
+    fn accept(&self) -> io::Result<(Self::Stream, Self::Addr)>;
+}
+
+impl Accept for TcpListener {
+    type Stream = TcpStream;
+    type Addr = SocketAddr;
+
+    fn accept(&self) -> io::Result<(TcpStream, SocketAddr)> {
+        TcpListener::accept(self)
+    }
+}
+
+impl Accept for UnixListener {
+    type Stream = UnixStream;
+    type Addr = net::SocketAddr;
+
+    fn accept(&self) -> io::Result<(UnixStream, net::SocketAddr)> {
+        UnixListener::accept(self)
+    }
+}
+
+/// Accepts connections and survives running out of file descriptors.
+///
+/// When accept fails with EMFILE or ENFILE, the pending connection stays in
+/// the backlog and a level-triggered listener is reported readable forever.
+/// `Acceptor` keeps a reserve fd: it closes the reserve, accepts and drops
+/// the pending connection, then opens the reserve again. The listener is
+/// then removed from the epoll until a `TimerFd` expires, the backoff doubles
+/// on every failure up to a maximum and is reset by the next accept.
+///
+/// The listener and the timer are registered with the same token, call
+/// `accept` whenever the token is reported.
+///
+/// # Example
+///
+/// ```
+/// use queen_io::epoll::{Epoll, EpollOpt, Events, Ready, Token};
+/// use queen_io::net::tcp::TcpListener;
+/// use queen_io::net::Acceptor;
+///
+/// let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+/// let mut acceptor = Acceptor::new(listener).unwrap();
+///
+/// let epoll = Epoll::new().unwrap();
+/// epoll.add(&acceptor, Token(0), Ready::readable(), EpollOpt::level()).unwrap();
+///
+/// match acceptor.accept(&epoll) {
+///     Ok((_stream, _addr)) => (),
+///     Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => (),
+///     // out of file descriptors, the connection was dropped
+///     Err(e) => eprintln!("accept: {}", e),
+/// }
+/// ```
+#[derive(Debug)]
+pub struct Acceptor<L> {
+    listener: L,
+    reserve: Option<File>,
+    timer: TimerFd,
+    min_backoff: Duration,
+    max_backoff: Duration,
+    backoff: Duration,
+    paused: bool,
+    registration: Cell<Option<(Token, Ready, EpollOpt)>>,
+}
+
+impl<L: Accept> Acceptor<L> {
+    pub fn new(listener: L) -> io::Result<Acceptor<L>> {
+        let min_backoff = Duration::from_millis(10);
+
+        Ok(Acceptor {
+            listener,
+            reserve: Some(open_reserve()?),
+            timer: TimerFd::new()?,
+            min_backoff,
+            max_backoff: Duration::from_secs(1),
+            backoff: min_backoff,
+            paused: false,
+            registration: Cell::new(None),
+        })
+    }
+
+    /// Set the first and the largest pause, 10ms and 1s by default.
+    pub fn set_backoff(&mut self, min: Duration, max: Duration) {
+        self.min_backoff = min;
+        self.max_backoff = max.max(min);
+        self.backoff = min;
+    }
+
+    pub fn get_ref(&self) -> &L {
+        &self.listener
+    }
+
+    pub fn into_inner(self) -> L {
+        self.listener
+    }
+
+    /// Whether the listener interest is paused after running out of file
+    /// descriptors.
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Accept a connection.
+    ///
+    /// `WouldBlock` is returned if no connection is pending or while the
+    /// listener is paused. On EMFILE or ENFILE the pending connection is
+    /// dropped, the listener is paused and the error is returned.
+    pub fn accept(&mut self, epoll: &Epoll) -> io::Result<(L::Stream, L::Addr)> {
+        if self.paused {
+            self.timer.read()?;
+            self.resume(epoll)?;
+        }
+
+        match self.listener.accept() {
+            Ok(conn) => {
+                self.backoff = self.min_backoff;
+                Ok(conn)
+            }
+            Err(e) if is_fd_exhausted(&e) => {
+                self.shed();
+                self.pause(epoll)?;
+                Err(e)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    fn shed(&mut self) {
+        self.reserve = None;
+
+        if let Ok(conn) = self.listener.accept() {
+            drop(conn);
+        }
+
+        self.reserve = open_reserve().ok();
+    }
+
+    fn pause(&mut self, epoll: &Epoll) -> io::Result<()> {
+        if self.registration.get().is_some() {
+            self.listener.delete(epoll)?;
+        }
+
+        self.timer.set_after(self.backoff)?;
+        self.backoff = (self.backoff * 2).min(self.max_backoff);
+        self.paused = true;
+
+        Ok(())
+    }
+
+    fn resume(&mut self, epoll: &Epoll) -> io::Result<()> {
+        if let Some((token, interest, opts)) = self.registration.get() {
+            self.listener.add(epoll, token, interest, opts)?;
+        }
+
+        self.paused = false;
+
+        Ok(())
+    }
+}
+
+fn open_reserve() -> io::Result<File> {
+    File::open("/dev/null")
+}
+
+fn is_fd_exhausted(err: &io::Error) -> bool {
+    matches!(err.raw_os_error(), Some(libc::EMFILE) | Some(libc::ENFILE))
+}
+
+impl<L: Accept> Source for Acceptor<L> {
+    fn add(&self, epoll: &Epoll, token: Token, interest: Ready, opts: EpollOpt) -> io::Result<()> {
+        if !self.paused {
+            self.listener.add(epoll, token, interest, opts)?;
+        }
+
+        epoll.add(&self.timer.as_raw_fd(), token, Ready::readable(), opts)?;
+        self.registration.set(Some((token, interest, opts)));
+
+        Ok(())
+    }
+
+    fn modify(
+        &self,
+        epoll: &Epoll,
+        token: Token,
+        interest: Ready,
+        opts: EpollOpt,
+    ) -> io::Result<()> {
+        if !self.paused {
+            self.listener.modify(epoll, token, interest, opts)?;
+        }
+
+        epoll.modify(&self.timer.as_raw_fd(), token, Ready::readable(), opts)?;
+        self.registration.set(Some((token, interest, opts)));
+
+        Ok(())
+    }
+
+    fn delete(&self, epoll: &Epoll) -> io::Result<()> {
+        self.registration.set(None);
+
+        if !self.paused {
+            self.listener.delete(epoll)?;
+        }
+
+        epoll.delete(&self.timer.as_raw_fd())
+    }
+}
+
+impl<L: AsRawFd> AsRawFd for Acceptor<L> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.listener.as_raw_fd()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::fs::File;
+    use std::io::{ErrorKind, Read};
+    use std::mem;
+    use std::net;
+    use std::process;
+    use std::time::Duration;
+
+    use crate::epoll::{Epoll, EpollOpt, Events, Ready, Token};
+    use crate::net::tcp::TcpListener;
+
+    use super::Acceptor;
+
+    #[test]
+    fn accept() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mut acceptor = Acceptor::new(listener).unwrap();
+
+        let epoll = Epoll::new().unwrap();
+        let mut events = Events::with_capacity(8);
+        epoll
+            .add(&acceptor, Token(1), Ready::readable(), EpollOpt::level())
+            .unwrap();
+
+        let err = acceptor.accept(&epoll).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::WouldBlock);
+
+        let client = net::TcpStream::connect(addr).unwrap();
+
+        epoll
+            .wait(&mut events, Some(Duration::from_secs(5)))
+            .unwrap();
+        assert_eq!(events.len(), 1);
+
+        let (_stream, peer) = acceptor.accept(&epoll).unwrap();
+        assert_eq!(peer, client.local_addr().unwrap());
+        assert!(!acceptor.is_paused());
+    }
+
+    #[test]
+    fn shed_on_emfile() {
+        // lowering RLIMIT_NOFILE would break the other tests
+        if env::var_os("QUEEN_IO_TEST_ISOLATED").is_none() {
+            let output = process::Command::new(env::current_exe().unwrap())
+                .args(["--exact", "net::accept::test::shed_on_emfile"])
+                .env("QUEEN_IO_TEST_ISOLATED", "1")
+                .output()
+                .unwrap();
+
+            assert!(output.status.success(), "{:?}", output);
+            return;
+        }
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mut acceptor = Acceptor::new(listener).unwrap();
+        acceptor.set_backoff(Duration::from_millis(50), Duration::from_millis(100));
+
+        let epoll = Epoll::new().unwrap();
+        let mut events = Events::with_capacity(8);
+        epoll
+            .add(&acceptor, Token(1), Ready::readable(), EpollOpt::level())
+            .unwrap();
+
+        let mut shed = net::TcpStream::connect(addr).unwrap();
+        shed.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+
+        // use up every file descriptor
+        let mut limit: libc::rlimit = unsafe { mem::zeroed() };
+        assert_eq!(unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) }, 0);
+        let saved = limit;
+        limit.rlim_cur = limit.rlim_cur.min(256);
+        assert_eq!(unsafe { libc::setrlimit(libc::RLIMIT_NOFILE, &limit) }, 0);
+
+        let mut files = Vec::new();
+        loop {
+            match File::open("/dev/null") {
+                Ok(file) => files.push(file),
+                Err(e) => {
+                    assert_eq!(e.raw_os_error(), Some(libc::EMFILE));
+                    break;
+                }
+            }
+        }
+
+        let err = acceptor.accept(&epoll).unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::EMFILE));
+        assert!(acceptor.is_paused());
+
+        // the pending connection was accepted and closed
+        let mut buf = [0u8; 8];
+        assert_eq!(shed.read(&mut buf).unwrap(), 0);
+
+        drop(files);
+        assert_eq!(unsafe { libc::setrlimit(libc::RLIMIT_NOFILE, &saved) }, 0);
+
+        let client = net::TcpStream::connect(addr).unwrap();
+
+        // the listener is not reported until the backoff expires
+        epoll
+            .wait(&mut events, Some(Duration::from_millis(10)))
+            .unwrap();
+        assert!(events.is_empty());
+
+        let err = acceptor.accept(&epoll).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::WouldBlock);
+
+        epoll
+            .wait(&mut events, Some(Duration::from_secs(5)))
+            .unwrap();
+        assert_eq!(events.len(), 1);
+
+        let (_stream, peer) = acceptor.accept(&epoll).unwrap();
+        assert_eq!(peer, client.local_addr().unwrap());
+        assert!(!acceptor.is_paused());
+    }
+}
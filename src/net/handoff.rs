@@ -0,0 +1,491 @@
+//! Zero-downtime restart by handing listening sockets to a new process.
+//!
+//! The running process binds a `HandoffSocket`. When a new version starts,
+//! it calls `receive` with the same path: the old process accepts it as a
+//! `Successor` and hands over its `TcpListener`s and `UnixListener`s
+//! (SCM_RIGHTS) along with a state blob. The listen queues are shared, no
+//! connection is refused in between. Once the new process acknowledged, the
+//! old one stops accepting, drains its connections and exits.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use queen_io::net::handoff::{self, HandoffSocket, Listener};
+//! use queen_io::net::tcp::TcpListener;
+//! use std::time::Duration;
+//!
+//! let path = "/run/proxy/handoff.sock";
+//!
+//! // in the new process: take over the listeners of the old one, if any
+//! let listener = match handoff::receive(path, Duration::from_secs(5)).unwrap() {
+//!     Some(handoff) => match handoff.listeners.into_iter().next() {
+//!         Some(Listener::Tcp(listener)) => listener,
+//!         _ => panic!("unexpected listeners"),
+//!     },
+//!     None => TcpListener::bind("0.0.0.0:8080").unwrap(),
+//! };
+//!
+//! let handoff_socket = HandoffSocket::bind(path).unwrap();
+//!
+//! // later, when `handoff_socket` is readable, a successor has started
+//! let successor = handoff_socket.accept().unwrap();
+//! drop(handoff_socket);
+//! successor.hand_off(&[(&listener).into()], b"state").unwrap();
+//! // stop accepting, drain the connections and exit
+//! ```
+
+use std::io::{self, Read, Write};
+use std::os::unix::io::{AsRawFd, FromRawFd, IntoRawFd, OwnedFd, RawFd};
+use std::os::unix::net::SocketAddr;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::epoll::{Epoll, EpollOpt, Ready, Source, Token};
+use crate::sys::socket;
+
+use super::tcp::TcpListener;
+use super::unix::{UCred, UnixListener, UnixStream, MAX_FDS};
+
+const MAGIC: &[u8; 4] = b"QIOH";
+const VERSION: u8 = 1;
+const HEADER_LEN: usize = 10;
+const ACK: u8 = 1;
+
+const KIND_TCP: u8 = 0;
+const KIND_UNIX: u8 = 1;
+
+/// The largest state blob accepted by `receive`.
+pub const MAX_STATE: usize = 16 * 1024 * 1024;
+
+/// The timeout of each read and write of the old process during a handoff.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A listener received from the old process.
+#[derive(Debug)]
+pub enum Listener {
+    Tcp(TcpListener),
+    Unix(UnixListener),
+}
+
+/// A listener to hand over, see `Successor::hand_off`.
+#[derive(Debug, Clone, Copy)]
+pub enum ListenerRef<'a> {
+    Tcp(&'a TcpListener),
+    Unix(&'a UnixListener),
+}
+
+impl<'a> From<&'a TcpListener> for ListenerRef<'a> {
+    fn from(listener: &'a TcpListener) -> ListenerRef<'a> {
+        ListenerRef::Tcp(listener)
+    }
+}
+
+impl<'a> From<&'a UnixListener> for ListenerRef<'a> {
+    fn from(listener: &'a UnixListener) -> ListenerRef<'a> {
+        ListenerRef::Unix(listener)
+    }
+}
+
+impl ListenerRef<'_> {
+    fn kind(&self) -> u8 {
+        match self {
+            ListenerRef::Tcp(_) => KIND_TCP,
+            ListenerRef::Unix(_) => KIND_UNIX,
+        }
+    }
+
+    fn as_raw_fd(&self) -> RawFd {
+        match self {
+            ListenerRef::Tcp(listener) => listener.as_raw_fd(),
+            ListenerRef::Unix(listener) => listener.as_raw_fd(),
+        }
+    }
+}
+
+/// What the new process received from the old one.
+#[derive(Debug)]
+pub struct Handoff {
+    /// The listeners, in the order they were handed over.
+    pub listeners: Vec<Listener>,
+    pub state: Vec<u8>,
+}
+
+/// The control socket on which a running process waits for its successor.
+///
+/// The socket file is only accessible to the owner, and connections from
+/// other users are rejected. It is removed when the socket is dropped.
+#[derive(Debug)]
+pub struct HandoffSocket {
+    listener: UnixListener,
+}
+
+impl HandoffSocket {
+    /// Bind the control socket to `path`, a socket file left by a process
+    /// which is gone is replaced.
+    pub fn bind<P: AsRef<Path>>(path: P) -> io::Result<HandoffSocket> {
+        let mut listener = UnixListener::builder()
+            .mode(0o600)
+            .remove_stale(true)
+            .unlink_on_drop(true)
+            .bind(path)?;
+
+        let uid = UCred::current().uid;
+        listener.set_cred_filter(move |cred| cred.uid == uid);
+
+        Ok(HandoffSocket { listener })
+    }
+
+    /// Accept a new process calling `receive`, `WouldBlock` if none is
+    /// waiting.
+    ///
+    /// Drop the `HandoffSocket` before `Successor::hand_off`, so that the new
+    /// process can bind the path for its own successor.
+    pub fn accept(&self) -> io::Result<Successor> {
+        let (stream, _) = self.listener.accept()?;
+
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+
+        Ok(Successor { stream })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+}
+
+impl Source for HandoffSocket {
+    fn add(&self, epoll: &Epoll, token: Token, interest: Ready, opts: EpollOpt) -> io::Result<()> {
+        self.listener.add(epoll, token, interest, opts)
+    }
+
+    fn modify(
+        &self,
+        epoll: &Epoll,
+        token: Token,
+        interest: Ready,
+        opts: EpollOpt,
+    ) -> io::Result<()> {
+        self.listener.modify(epoll, token, interest, opts)
+    }
+
+    fn delete(&self, epoll: &Epoll) -> io::Result<()> {
+        self.listener.delete(epoll)
+    }
+}
+
+impl AsRawFd for HandoffSocket {
+    fn as_raw_fd(&self) -> RawFd {
+        self.listener.as_raw_fd()
+    }
+}
+
+/// A new process waiting for the listeners, see `HandoffSocket::accept`.
+#[derive(Debug)]
+pub struct Successor {
+    stream: UnixStream,
+}
+
+impl Successor {
+    /// Send `listeners` and `state` to the new process, and wait until it has
+    /// taken them over.
+    ///
+    /// The listeners stay open in this process: on error they can still be
+    /// used, on success stop accepting on them, drain the connections and
+    /// exit. The socket files of the unix listeners are no longer removed on
+    /// drop, they belong to the new process.
+    ///
+    /// An error does not always mean the new process gave up: if the
+    /// acknowledgement arrives just after `TIMEOUT`, both processes end up
+    /// with the listeners. In any other case an interrupted exchange closes
+    /// the stream, and `receive` fails and closes the listeners it got.
+    pub fn hand_off(self, listeners: &[ListenerRef], state: &[u8]) -> io::Result<()> {
+        if listeners.len() > MAX_FDS {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "too many listeners",
+            ));
+        }
+
+        if state.len() > MAX_STATE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "state is too large",
+            ));
+        }
+
+        let mut message = Vec::with_capacity(HEADER_LEN + listeners.len());
+        message.extend_from_slice(MAGIC);
+        message.push(VERSION);
+        message.push(listeners.len() as u8);
+        message.extend_from_slice(&(state.len() as u32).to_le_bytes());
+        message.extend(listeners.iter().map(ListenerRef::kind));
+
+        let fds: Vec<RawFd> = listeners.iter().map(ListenerRef::as_raw_fd).collect();
+
+        let n = self.stream.send_with_fds(&message, &fds)?;
+        (&self.stream).write_all(&message[n..])?;
+        (&self.stream).write_all(state)?;
+
+        let mut ack = [0];
+        (&self.stream).read_exact(&mut ack)?;
+
+        if ack[0] != ACK {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "invalid handoff acknowledgement",
+            ));
+        }
+
+        for listener in listeners {
+            if let ListenerRef::Unix(listener) = listener {
+                listener.keep_socket_file();
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The credentials of the new process.
+    pub fn peer_cred(&self) -> io::Result<UCred> {
+        self.stream.peer_cred()
+    }
+}
+
+/// Take over the listeners of the process running a `HandoffSocket` on
+/// `path`. `None` is returned if there is no such process, the listeners
+/// have to be bound then.
+///
+/// The old process must run as the same user, `PermissionDenied` is
+/// returned otherwise. `timeout` bounds each read and write of the exchange.
+///
+/// On error the received listeners are closed, the old process keeps
+/// serving on its own.
+pub fn receive<P: AsRef<Path>>(path: P, timeout: Duration) -> io::Result<Option<Handoff>> {
+    let stream = match UnixStream::connect(path) {
+        Ok(stream) => stream,
+        Err(ref e)
+            if e.kind() == io::ErrorKind::NotFound
+                || e.kind() == io::ErrorKind::ConnectionRefused =>
+        {
+            return Ok(None)
+        }
+        Err(e) => return Err(e),
+    };
+
+    if stream.peer_cred()?.uid != UCred::current().uid {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "handoff socket is owned by another user",
+        ));
+    }
+
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+
+    let mut fds = Vec::new();
+
+    let mut header = [0; HEADER_LEN];
+    read_exact_with_fds(&stream, &mut header, &mut fds)?;
+
+    if &header[..4] != MAGIC || header[4] != VERSION {
+        return Err(invalid_data("invalid handoff header"));
+    }
+
+    let count = header[5] as usize;
+    let state_len = u32::from_le_bytes([header[6], header[7], header[8], header[9]]) as usize;
+
+    if state_len > MAX_STATE {
+        return Err(invalid_data("handoff state is too large"));
+    }
+
+    let mut kinds = vec![0; count];
+    read_exact_with_fds(&stream, &mut kinds, &mut fds)?;
+
+    let mut state = vec![0; state_len];
+    read_exact_with_fds(&stream, &mut state, &mut fds)?;
+
+    if fds.len() != count {
+        return Err(invalid_data("handoff file descriptors are missing"));
+    }
+
+    let listeners = kinds
+        .into_iter()
+        .zip(fds)
+        .map(|(kind, fd)| to_listener(kind, fd))
+        .collect::<io::Result<Vec<_>>>()?;
+
+    (&stream).write_all(&[ACK])?;
+
+    Ok(Some(Handoff { listeners, state }))
+}
+
+fn read_exact_with_fds(
+    stream: &UnixStream,
+    mut buf: &mut [u8],
+    fds: &mut Vec<OwnedFd>,
+) -> io::Result<()> {
+    while !buf.is_empty() {
+        match stream.recv_with_fds(buf, fds) {
+            Ok(0) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "handoff interrupted",
+                ));
+            }
+            Ok(n) => buf = &mut buf[n..],
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(())
+}
+
+fn to_listener(kind: u8, fd: OwnedFd) -> io::Result<Listener> {
+    let domain: libc::c_int =
+        socket::getsockopt(fd.as_raw_fd(), libc::SOL_SOCKET, libc::SO_DOMAIN)?;
+    let listening: libc::c_int =
+        socket::getsockopt(fd.as_raw_fd(), libc::SOL_SOCKET, libc::SO_ACCEPTCONN)?;
+
+    if listening == 0 {
+        return Err(invalid_data("handoff socket is not listening"));
+    }
+
+    match (kind, domain) {
+        (KIND_TCP, libc::AF_INET) | (KIND_TCP, libc::AF_INET6) => Ok(Listener::Tcp(unsafe {
+            TcpListener::from_raw_fd(fd.into_raw_fd())
+        })),
+        (KIND_UNIX, libc::AF_UNIX) => Ok(Listener::Unix(unsafe {
+            UnixListener::from_raw_fd(fd.into_raw_fd())
+        })),
+        _ => Err(invalid_data("handoff socket has an unexpected type")),
+    }
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::ErrorKind;
+    use std::net;
+    use std::thread;
+    use std::time::Duration;
+
+    use crate::epoll::{Epoll, EpollOpt, Events, Ready, Token};
+    use crate::net::tcp::TcpListener;
+    use crate::net::unix::{UnixListener, UnixStream};
+
+    use super::{receive, HandoffSocket, Listener};
+
+    #[test]
+    fn hand_off() {
+        let dir = std::env::temp_dir();
+        let path = dir.join(format!("queen-io-handoff-{}.sock", std::process::id()));
+        let unix_path = dir.join(format!("queen-io-handoff-{}-unix.sock", std::process::id()));
+        let _ = std::fs::remove_file(&unix_path);
+
+        assert!(receive(&path, Duration::from_secs(1)).unwrap().is_none());
+
+        let tcp = TcpListener::bind("127.0.0.1:0").unwrap();
+        let tcp_addr = tcp.local_addr().unwrap();
+        let unix = UnixListener::builder()
+            .unlink_on_drop(true)
+            .bind(&unix_path)
+            .unwrap();
+
+        let handoff_socket = HandoffSocket::bind(&path).unwrap();
+
+        let new_process = {
+            let path = path.clone();
+            thread::spawn(move || receive(path, Duration::from_secs(5)).unwrap().unwrap())
+        };
+
+        let epoll = Epoll::new().unwrap();
+        let mut events = Events::with_capacity(8);
+        epoll
+            .add(
+                &handoff_socket,
+                Token(0),
+                Ready::readable(),
+                EpollOpt::level(),
+            )
+            .unwrap();
+        epoll
+            .wait(&mut events, Some(Duration::from_secs(5)))
+            .unwrap();
+
+        let successor = handoff_socket.accept().unwrap();
+        drop(handoff_socket);
+        assert!(!path.exists());
+
+        successor
+            .hand_off(&[(&tcp).into(), (&unix).into()], b"sessions")
+            .unwrap();
+
+        let handoff = new_process.join().unwrap();
+        assert_eq!(handoff.state, b"sessions");
+        assert_eq!(handoff.listeners.len(), 2);
+
+        // the old process exits
+        drop(tcp);
+        drop(unix);
+        assert!(unix_path.exists());
+
+        let _client = net::TcpStream::connect(tcp_addr).unwrap();
+        let _unix_client = UnixStream::connect(&unix_path).unwrap();
+
+        for listener in handoff.listeners {
+            match listener {
+                Listener::Tcp(listener) => {
+                    assert_eq!(listener.local_addr().unwrap(), tcp_addr);
+                    assert!(listener.accept().is_ok());
+                }
+                Listener::Unix(listener) => {
+                    assert!(listener.accept().is_ok());
+                }
+            }
+        }
+
+        std::fs::remove_file(&unix_path).unwrap();
+    }
+
+    #[test]
+    fn interrupted() {
+        let path = std::env::temp_dir().join(format!(
+            "queen-io-handoff-{}-interrupted.sock",
+            std::process::id()
+        ));
+
+        let handoff_socket = HandoffSocket::bind(&path).unwrap();
+
+        let new_process = {
+            let path = path.clone();
+            thread::spawn(move || receive(path, Duration::from_secs(5)))
+        };
+
+        let epoll = Epoll::new().unwrap();
+        let mut events = Events::with_capacity(8);
+        epoll
+            .add(
+                &handoff_socket,
+                Token(0),
+                Ready::readable(),
+                EpollOpt::level(),
+            )
+            .unwrap();
+        epoll
+            .wait(&mut events, Some(Duration::from_secs(5)))
+            .unwrap();
+
+        // the old process gives up before sending anything
+        drop(handoff_socket.accept().unwrap());
+
+        let err = new_process.join().unwrap().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+    }
+}
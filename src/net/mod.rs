@@ -1,2 +1,10 @@
+pub use self::accept::{Accept, Acceptor};
+pub use self::udp::UdpSocket;
+pub use self::zero_copy::{sendfile, SplicePipe};
+
+pub mod accept;
+pub mod handoff;
 pub mod tcp;
+pub mod udp;
 pub mod unix;
+pub mod zero_copy;
@@ -1,10 +1,15 @@
 use std::io;
 use std::io::{Read, Write};
+use std::mem;
 use std::net::{self, SocketAddr, ToSocketAddrs};
 use std::os::unix::io::{AsRawFd, FromRawFd, IntoRawFd, RawFd};
 use std::time::Duration;
 
 use crate::epoll::{Epoll, EpollOpt, Ready, SelectorId, Source, Token};
+use crate::sys::cmsg::CmsgBuf;
+use crate::sys::socket::{
+    self, BindToDevice, Ipv6V6Only, ReuseAddr, ReusePort, SockOpt, Timestamps,
+};
 
 #[derive(Debug)]
 pub struct TcpStream {
@@ -25,6 +30,29 @@ impl TcpStream {
         TcpStream::new(stream)
     }
 
+    /// Start connecting to `addr` without blocking.
+    ///
+    /// The socket is created with SOCK_NONBLOCK and the stream is returned as
+    /// soon as the kernel reports EINPROGRESS. Register it for
+    /// `Ready::writable()`, once it is writable `take_error` returns the
+    /// result of the connect: `None` if the connection is established.
+    pub fn connect_nonblocking(addr: &SocketAddr) -> io::Result<TcpStream> {
+        let fd = socket::socket(socket::domain(addr), libc::SOCK_STREAM, 0)?;
+        let stream = unsafe { TcpStream::from_raw_fd(fd) };
+
+        let (storage, len) = socket::socket_addr(addr);
+
+        match syscall!(connect(fd, &storage as *const _ as *const libc::sockaddr, len)) {
+            Ok(_) => (),
+            Err(ref e) if e.raw_os_error() == Some(libc::EINPROGRESS) => (),
+            // the connect goes on in the background, as with EINPROGRESS
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
+            Err(e) => return Err(e),
+        }
+
+        Ok(stream)
+    }
+
     pub fn connect_timeout(addr: &SocketAddr, timeout: Duration) -> io::Result<TcpStream> {
         let stream = net::TcpStream::connect_timeout(addr, timeout)?;
 
@@ -86,6 +114,22 @@ impl TcpStream {
         self.inner.peek(buf)
     }
 
+    /// Read into `buf` with recvmsg(2), along with the kernel receive
+    /// timestamps of the last bytes read when `TimestampNs` or `Timestamping`
+    /// is enabled.
+    pub fn recv_msg(&self, buf: &mut [u8]) -> io::Result<(usize, Timestamps)> {
+        let mut control = CmsgBuf::with_capacity(Timestamps::cmsg_space());
+        let (len, _, _) = socket::recvmsg::<()>(self.as_raw_fd(), buf, None, &mut control, 0)?;
+
+        let mut timestamps = Timestamps::default();
+
+        for cmsg in control.iter() {
+            timestamps.parse(&cmsg);
+        }
+
+        Ok((len, timestamps))
+    }
+
     pub fn set_nodelay(&self, nodelay: bool) -> io::Result<()> {
         self.inner.set_nodelay(nodelay)
     }
@@ -106,6 +150,14 @@ impl TcpStream {
         self.inner.take_error()
     }
 
+    pub fn set_sockopt<O: SockOpt>(&self, opt: O, value: O::Value) -> io::Result<()> {
+        opt.set(self.as_raw_fd(), value)
+    }
+
+    pub fn sockopt<O: SockOpt>(&self, opt: O) -> io::Result<O::Value> {
+        opt.get(self.as_raw_fd())
+    }
+
     pub fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
         self.inner.set_nonblocking(nonblocking)
     }
@@ -117,7 +169,7 @@ impl Read for TcpStream {
     }
 }
 
-impl<'a> Read for &'a TcpStream {
+impl Read for &TcpStream {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
         (&self.inner).read(buf)
     }
@@ -133,7 +185,7 @@ impl Write for TcpStream {
     }
 }
 
-impl<'a> Write for &'a TcpStream {
+impl Write for &TcpStream {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
         (&self.inner).write(buf)
     }
@@ -185,7 +237,116 @@ impl AsRawFd for TcpStream {
     }
 }
 
+/// Configures the socket of a `TcpListener` before it is bound.
+///
+/// The socket is created non-blocking and close-on-exec, SO_REUSEADDR is set
+/// by default, as std does.
+///
+/// # Example
+///
+/// ```
+/// use queen_io::net::tcp::TcpListenerBuilder;
+///
+/// let addr = "127.0.0.1:0".parse().unwrap();
+///
+/// let listener = TcpListenerBuilder::new()
+///     .reuse_port(true)
+///     .backlog(4096)
+///     .bind(&addr)
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct TcpListenerBuilder {
+    reuse_addr: bool,
+    reuse_port: bool,
+    only_v6: Option<bool>,
+    device: Option<String>,
+    backlog: i32,
+}
+
+impl TcpListenerBuilder {
+    pub fn new() -> TcpListenerBuilder {
+        TcpListenerBuilder {
+            reuse_addr: true,
+            reuse_port: false,
+            only_v6: None,
+            device: None,
+            backlog: 128,
+        }
+    }
+
+    /// SO_REUSEADDR, true by default.
+    pub fn reuse_addr(&mut self, reuse: bool) -> &mut TcpListenerBuilder {
+        self.reuse_addr = reuse;
+        self
+    }
+
+    /// SO_REUSEPORT, several listeners bound to the same address share the
+    /// incoming connections.
+    pub fn reuse_port(&mut self, reuse: bool) -> &mut TcpListenerBuilder {
+        self.reuse_port = reuse;
+        self
+    }
+
+    /// IPV6_V6ONLY, only applied to IPv6 addresses. By default the system
+    /// setting is kept.
+    pub fn only_v6(&mut self, only_v6: bool) -> &mut TcpListenerBuilder {
+        self.only_v6 = Some(only_v6);
+        self
+    }
+
+    /// SO_BINDTODEVICE, only accept connections arriving on `interface`.
+    pub fn bind_device(&mut self, interface: &str) -> &mut TcpListenerBuilder {
+        self.device = Some(interface.to_owned());
+        self
+    }
+
+    /// The backlog passed to listen(2), 128 by default. The kernel caps it at
+    /// `net.core.somaxconn`.
+    pub fn backlog(&mut self, backlog: i32) -> &mut TcpListenerBuilder {
+        self.backlog = backlog;
+        self
+    }
+
+    pub fn bind(&self, addr: &SocketAddr) -> io::Result<TcpListener> {
+        let fd = socket::socket(socket::domain(addr), libc::SOCK_STREAM, 0)?;
+        let listener = unsafe { TcpListener::from_raw_fd(fd) };
+
+        if self.reuse_addr {
+            listener.set_sockopt(ReuseAddr, true)?;
+        }
+
+        if self.reuse_port {
+            listener.set_sockopt(ReusePort, true)?;
+        }
+
+        if let (Some(only_v6), SocketAddr::V6(_)) = (self.only_v6, addr) {
+            listener.set_sockopt(Ipv6V6Only, only_v6)?;
+        }
+
+        if let Some(ref device) = self.device {
+            listener.set_sockopt(BindToDevice, Some(device.clone()))?;
+        }
+
+        let (storage, len) = socket::socket_addr(addr);
+        syscall!(bind(fd, &storage as *const _ as *const libc::sockaddr, len))?;
+        syscall!(listen(fd, self.backlog))?;
+
+        Ok(listener)
+    }
+}
+
+impl Default for TcpListenerBuilder {
+    fn default() -> TcpListenerBuilder {
+        TcpListenerBuilder::new()
+    }
+}
+
 impl TcpListener {
+    pub fn builder() -> TcpListenerBuilder {
+        TcpListenerBuilder::new()
+    }
+
     pub fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<TcpListener> {
         let listener = net::TcpListener::bind(addr)?;
 
@@ -212,10 +373,26 @@ impl TcpListener {
         })
     }
 
+    /// Accept a connection with accept4(2), the stream is non-blocking and
+    /// close-on-exec without further syscalls.
     pub fn accept(&self) -> io::Result<(TcpStream, SocketAddr)> {
-        self.inner
-            .accept()
-            .and_then(|(s, a)| Ok((TcpStream::new(s)?, a)))
+        let mut storage: libc::sockaddr_storage = unsafe { mem::zeroed() };
+        let (fd, len) = socket::accept4(self.as_raw_fd(), &mut storage)?;
+
+        let stream = unsafe { TcpStream::from_raw_fd(fd) };
+        let addr = socket::to_socket_addr(&storage, len)?;
+
+        Ok((stream, addr))
+    }
+
+    /// Accept up to `max` connections, until the backlog is drained.
+    ///
+    /// `WouldBlock` is only returned if no connection was pending. An error
+    /// after some connections were accepted ends the batch, it is returned
+    /// by the next call. Connections aborted before they were accepted are
+    /// skipped.
+    pub fn accept_batch(&self, max: usize) -> io::Result<Vec<(TcpStream, SocketAddr)>> {
+        socket::accept_batch(max, || self.accept())
     }
 
     pub fn set_ttl(&self, ttl: u32) -> io::Result<()> {
@@ -229,6 +406,14 @@ impl TcpListener {
     pub fn take_error(&self) -> io::Result<Option<io::Error>> {
         self.inner.take_error()
     }
+
+    pub fn set_sockopt<O: SockOpt>(&self, opt: O, value: O::Value) -> io::Result<()> {
+        opt.set(self.as_raw_fd(), value)
+    }
+
+    pub fn sockopt<O: SockOpt>(&self, opt: O) -> io::Result<O::Value> {
+        opt.get(self.as_raw_fd())
+    }
 }
 
 impl Source for TcpListener {
@@ -272,3 +457,132 @@ impl AsRawFd for TcpListener {
         self.inner.as_raw_fd()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::io::ErrorKind;
+    use std::net;
+    use std::time::Duration;
+
+    use crate::epoll::{Epoll, EpollOpt, Events, Ready, Token};
+
+    use super::{TcpListener, TcpStream};
+
+    fn wait_writable(stream: &TcpStream) {
+        let epoll = Epoll::new().unwrap();
+        let mut events = Events::with_capacity(8);
+
+        epoll.add(stream, Token(0), Ready::writable(), EpollOpt::edge()).unwrap();
+        epoll.wait(&mut events, Some(Duration::from_secs(5))).unwrap();
+        assert_eq!(events.len(), 1);
+    }
+
+    #[test]
+    fn connect_nonblocking() {
+        let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let stream = TcpStream::connect_nonblocking(&addr).unwrap();
+        wait_writable(&stream);
+
+        assert!(stream.take_error().unwrap().is_none());
+        assert_eq!(stream.peer_addr().unwrap(), addr);
+    }
+
+    #[test]
+    fn connect_nonblocking_refused() {
+        let addr = net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+
+        let stream = TcpStream::connect_nonblocking(&addr).unwrap();
+        wait_writable(&stream);
+
+        let err = stream.take_error().unwrap().unwrap();
+        assert_eq!(err.kind(), ErrorKind::ConnectionRefused);
+    }
+
+    #[test]
+    fn builder_reuse_port() {
+        let addr = "127.0.0.1:0".parse().unwrap();
+
+        let listener1 = TcpListener::builder().reuse_port(true).bind(&addr).unwrap();
+        let addr = listener1.local_addr().unwrap();
+        let listener2 = TcpListener::builder().reuse_port(true).bind(&addr).unwrap();
+
+        assert_eq!(listener2.local_addr().unwrap(), addr);
+        assert!(TcpListener::builder().bind(&addr).is_err());
+
+        let err = listener1.accept().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::WouldBlock);
+    }
+
+    #[test]
+    fn accept_batch() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let err = listener.accept_batch(8).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::WouldBlock);
+
+        let clients: Vec<_> = (0..3).map(|_| net::TcpStream::connect(addr).unwrap()).collect();
+
+        let accepted = listener.accept_batch(2).unwrap();
+        assert_eq!(accepted.len(), 2);
+        assert_eq!(accepted[0].1, clients[0].local_addr().unwrap());
+
+        let accepted = listener.accept_batch(8).unwrap();
+        assert_eq!(accepted.len(), 1);
+        assert_eq!(accepted[0].0.peer_addr().unwrap(), clients[2].local_addr().unwrap());
+    }
+
+    #[test]
+    #[cfg(so_timestamping)]
+    fn recv_timestamps() {
+        use std::io::Write;
+        use std::thread;
+        use std::time::SystemTime;
+
+        use crate::sys::socket::Timestamping;
+
+        let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (mut peer, _) = listener.accept().unwrap();
+
+        let flags = libc::SOF_TIMESTAMPING_RX_SOFTWARE | libc::SOF_TIMESTAMPING_SOFTWARE;
+        stream.set_sockopt(Timestamping, flags).unwrap();
+        assert_eq!(stream.sockopt(Timestamping).unwrap() & flags, flags);
+
+        let epoll = Epoll::new().unwrap();
+        let mut events = Events::with_capacity(8);
+        epoll
+            .add(&stream, Token(0), Ready::readable(), EpollOpt::level())
+            .unwrap();
+
+        let mut buf = [0; 16];
+        let mut attempts = 0;
+
+        // the kernel turns receive timestamps on asynchronously, the first
+        // segments may not be stamped yet
+        let (before, timestamps) = loop {
+            let before = SystemTime::now();
+            peer.write_all(b"hello").unwrap();
+
+            epoll
+                .wait(&mut events, Some(Duration::from_secs(5)))
+                .unwrap();
+
+            let (n, timestamps) = stream.recv_msg(&mut buf).unwrap();
+            assert_eq!(&buf[..n], b"hello");
+
+            attempts += 1;
+            if timestamps.software.is_some() || attempts == 50 {
+                break (before, timestamps);
+            }
+
+            thread::sleep(Duration::from_millis(10));
+        };
+
+        let received = timestamps.software.unwrap();
+        assert!(received >= before - Duration::from_millis(10));
+        assert!(received <= SystemTime::now());
+    }
+}
@@ -0,0 +1,907 @@
+use std::fmt;
+use std::io;
+use std::mem;
+use std::net::{self, IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, ToSocketAddrs};
+use std::os::unix::io::{AsRawFd, FromRawFd, IntoRawFd, RawFd};
+use std::ptr;
+use std::slice::Chunks;
+
+use crate::epoll::{Epoll, EpollOpt, Ready, SelectorId, Source, Token};
+use crate::sys::cmsg::{self, CmsgBuf};
+use crate::sys::socket::{self, SockOpt, Timestamps};
+
+/// A non-blocking UDP socket.
+///
+/// # Example
+///
+/// ```
+/// use queen_io::net::UdpSocket;
+///
+/// let socket1 = UdpSocket::bind("127.0.0.1:0").unwrap();
+/// let socket2 = UdpSocket::bind("127.0.0.1:0").unwrap();
+///
+/// socket1.send_to(b"hello", socket2.local_addr().unwrap()).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct UdpSocket {
+    inner: net::UdpSocket,
+    selector_id: SelectorId,
+}
+
+impl UdpSocket {
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<UdpSocket> {
+        let socket = net::UdpSocket::bind(addr)?;
+
+        UdpSocket::new(socket)
+    }
+
+    pub fn new(socket: net::UdpSocket) -> io::Result<UdpSocket> {
+        socket.set_nonblocking(true)?;
+
+        Ok(UdpSocket {
+            inner: socket,
+            selector_id: SelectorId::new(),
+        })
+    }
+
+    pub fn from_socket(socket: net::UdpSocket) -> UdpSocket {
+        UdpSocket {
+            inner: socket,
+            selector_id: SelectorId::new(),
+        }
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.peer_addr()
+    }
+
+    pub fn try_clone(&self) -> io::Result<UdpSocket> {
+        self.inner.try_clone().map(|s| UdpSocket {
+            inner: s,
+            selector_id: self.selector_id.clone(),
+        })
+    }
+
+    pub fn send_to<A: ToSocketAddrs>(&self, buf: &[u8], target: A) -> io::Result<usize> {
+        self.inner.send_to(buf, target)
+    }
+
+    pub fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        self.inner.recv_from(buf)
+    }
+
+    pub fn peek_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        self.inner.peek_from(buf)
+    }
+
+    /// Set the default destination of `send` and only receive datagrams
+    /// from `addr`.
+    pub fn connect<A: ToSocketAddrs>(&self, addr: A) -> io::Result<()> {
+        self.inner.connect(addr)
+    }
+
+    pub fn send(&self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.send(buf)
+    }
+
+    pub fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.recv(buf)
+    }
+
+    pub fn peek(&self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.peek(buf)
+    }
+
+    /// Receive up to `batch.capacity()` datagrams with one recvmmsg(2) call.
+    /// view: `<http://man7.org/linux/man-pages/man2/recvmmsg.2.html>`
+    ///
+    /// The number of datagrams received is returned, `WouldBlock` if none
+    /// was queued. With an edge-triggered registration, call it again until
+    /// it returns `WouldBlock`.
+    pub fn recv_batch(&self, batch: &mut RecvBatch) -> io::Result<usize> {
+        batch.len = 0;
+
+        let RecvBatch {
+            bufs,
+            storages,
+            iovecs,
+            msgs,
+            ..
+        } = batch;
+
+        for (((buf, storage), iovec), msg) in bufs
+            .iter_mut()
+            .zip(storages.iter_mut())
+            .zip(iovecs.iter_mut())
+            .zip(msgs.iter_mut())
+        {
+            iovec.iov_base = buf.as_mut_ptr() as *mut libc::c_void;
+            iovec.iov_len = buf.len();
+
+            msg.msg_hdr.msg_name = storage as *mut _ as *mut libc::c_void;
+            msg.msg_hdr.msg_namelen = mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+            msg.msg_hdr.msg_iov = iovec;
+            msg.msg_hdr.msg_iovlen = 1;
+        }
+
+        let n = loop {
+            match syscall!(recvmmsg(
+                self.as_raw_fd(),
+                msgs.as_mut_ptr(),
+                msgs.len() as libc::c_uint,
+                0,
+                ptr::null_mut()
+            )) {
+                Ok(n) => break n as usize,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        };
+
+        for (i, msg) in batch.msgs.iter().take(n).enumerate() {
+            batch.lens[i] = msg.msg_len as usize;
+            batch.truncated[i] = msg.msg_hdr.msg_flags & libc::MSG_TRUNC != 0;
+            // the datagram is already received, keep it even if the address
+            // is not an inet one
+            batch.addrs[i] = socket::to_socket_addr(&batch.storages[i], msg.msg_hdr.msg_namelen)
+                .unwrap_or_else(|_| unspecified_addr());
+        }
+
+        batch.len = n;
+
+        Ok(n)
+    }
+
+    /// Send each buffer to its address with sendmmsg(2), `batch` holds the
+    /// message headers so that they are not allocated on every call.
+    /// view: `<http://man7.org/linux/man-pages/man2/sendmmsg.2.html>`
+    ///
+    /// The call is repeated until every datagram is sent, the socket would
+    /// block, or an error occurs. The number of datagrams sent is returned,
+    /// an error is only returned if nothing could be sent at all: call again
+    /// with the remaining datagrams to get it.
+    pub fn send_batch<B: AsRef<[u8]>>(
+        &self,
+        batch: &mut SendBatch,
+        msgs: &[(B, SocketAddr)],
+    ) -> io::Result<usize> {
+        let SendBatch {
+            addrs,
+            iovecs,
+            hdrs,
+        } = batch;
+
+        addrs.clear();
+        iovecs.clear();
+        hdrs.clear();
+
+        addrs.extend(msgs.iter().map(|(_, addr)| socket::socket_addr(addr)));
+
+        iovecs.extend(msgs.iter().map(|(buf, _)| libc::iovec {
+            iov_base: buf.as_ref().as_ptr() as *mut libc::c_void,
+            iov_len: buf.as_ref().len(),
+        }));
+
+        hdrs.extend(
+            iovecs
+                .iter_mut()
+                .zip(addrs.iter_mut())
+                .map(|(iovec, (storage, len))| {
+                    let mut msg: libc::mmsghdr = unsafe { mem::zeroed() };
+                    msg.msg_hdr.msg_name = storage as *mut _ as *mut libc::c_void;
+                    msg.msg_hdr.msg_namelen = *len;
+                    msg.msg_hdr.msg_iov = iovec;
+                    msg.msg_hdr.msg_iovlen = 1;
+                    msg
+                }),
+        );
+
+        let mut sent = 0;
+
+        while sent < hdrs.len() {
+            let remaining = &mut hdrs[sent..];
+
+            match syscall!(sendmmsg(
+                self.as_raw_fd(),
+                remaining.as_mut_ptr(),
+                remaining.len() as libc::c_uint,
+                0
+            )) {
+                Ok(n) => sent += n as usize,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) if sent == 0 => return Err(e),
+                Err(_) => break,
+            }
+        }
+
+        Ok(sent)
+    }
+
+    /// Receive a datagram with recvmsg(2), along with what the kernel
+    /// reports in control messages.
+    ///
+    /// With `UdpGro` enabled, several datagrams of the same flow may be
+    /// received at once, use `RecvMeta::segments` to split them. With
+    /// `IpPktInfo` or `Ipv6RecvPktInfo` enabled, the local address the
+    /// datagram was sent to and the interface it arrived on are reported.
+    /// With `TimestampNs` or `Timestamping` enabled, the time the kernel
+    /// received the datagram is reported.
+    pub fn recv_msg(&self, buf: &mut [u8]) -> io::Result<RecvMeta> {
+        let mut storage: libc::sockaddr_storage = unsafe { mem::zeroed() };
+        let mut control = CmsgBuf::with_capacity(
+            cmsg::space(mem::size_of::<libc::c_int>())
+                + cmsg::space(mem::size_of::<libc::in_pktinfo>())
+                + cmsg::space(mem::size_of::<libc::in6_pktinfo>())
+                + Timestamps::cmsg_space(),
+        );
+
+        let (len, namelen, flags) =
+            socket::recvmsg(self.as_raw_fd(), buf, Some(&mut storage), &mut control, 0)?;
+
+        let mut meta = RecvMeta {
+            len,
+            addr: socket::to_socket_addr(&storage, namelen)?,
+            truncated: flags & libc::MSG_TRUNC != 0,
+            control_truncated: flags & libc::MSG_CTRUNC != 0,
+            segment_size: None,
+            pktinfo: None,
+            timestamps: Timestamps::default(),
+        };
+
+        for cmsg in control.iter() {
+            if meta.timestamps.parse(&cmsg) {
+                continue;
+            }
+
+            match (cmsg.level, cmsg.ty) {
+                (libc::SOL_UDP, libc::UDP_GRO) => {
+                    meta.segment_size = cmsg.read::<libc::c_int>().map(|size| size as usize);
+                }
+                (libc::IPPROTO_IP, libc::IP_PKTINFO) => {
+                    meta.pktinfo = cmsg.read::<libc::in_pktinfo>().map(|info| PktInfo {
+                        addr: IpAddr::V4(Ipv4Addr::from(info.ipi_addr.s_addr.to_ne_bytes())),
+                        ifindex: info.ipi_ifindex as u32,
+                    });
+                }
+                (libc::IPPROTO_IPV6, libc::IPV6_PKTINFO) => {
+                    meta.pktinfo = cmsg.read::<libc::in6_pktinfo>().map(|info| PktInfo {
+                        addr: IpAddr::V6(Ipv6Addr::from(info.ipi6_addr.s6_addr)),
+                        ifindex: info.ipi6_ifindex,
+                    });
+                }
+                _ => (),
+            }
+        }
+
+        Ok(meta)
+    }
+
+    /// Send `buf` to `target` from the source address and interface of
+    /// `src`, usually the `pktinfo` of the request being answered. An
+    /// unspecified address or a 0 interface lets the kernel choose.
+    pub fn send_msg(&self, buf: &[u8], target: &SocketAddr, src: &PktInfo) -> io::Result<usize> {
+        let mut control = CmsgBuf::with_capacity(cmsg::space(mem::size_of::<libc::in6_pktinfo>()));
+
+        match src.addr {
+            IpAddr::V4(addr) => {
+                let info = libc::in_pktinfo {
+                    ipi_ifindex: src.ifindex as libc::c_int,
+                    ipi_spec_dst: libc::in_addr {
+                        s_addr: u32::from_ne_bytes(addr.octets()),
+                    },
+                    ipi_addr: libc::in_addr { s_addr: 0 },
+                };
+
+                control.push_value(libc::IPPROTO_IP, libc::IP_PKTINFO, &info);
+            }
+            IpAddr::V6(addr) => {
+                let info = libc::in6_pktinfo {
+                    ipi6_addr: libc::in6_addr {
+                        s6_addr: addr.octets(),
+                    },
+                    ipi6_ifindex: src.ifindex,
+                };
+
+                control.push_value(libc::IPPROTO_IPV6, libc::IPV6_PKTINFO, &info);
+            }
+        }
+
+        self.send_control(buf, target, &control)
+    }
+
+    /// Send `buf` to `target` as datagrams of `segment_size` bytes, the last
+    /// one may be shorter, with UDP segmentation offload: the kernel or the
+    /// NIC splits the buffer.
+    ///
+    /// The buffer may hold at most 64 segments and 64KiB.
+    pub fn send_segmented(
+        &self,
+        buf: &[u8],
+        segment_size: u16,
+        target: &SocketAddr,
+    ) -> io::Result<usize> {
+        let mut control = CmsgBuf::with_capacity(cmsg::space(mem::size_of::<u16>()));
+        control.push_value(libc::SOL_UDP, libc::UDP_SEGMENT, &segment_size);
+
+        self.send_control(buf, target, &control)
+    }
+
+    fn send_control(
+        &self,
+        buf: &[u8],
+        target: &SocketAddr,
+        control: &CmsgBuf,
+    ) -> io::Result<usize> {
+        let (storage, len) = socket::socket_addr(target);
+
+        socket::sendmsg(self.as_raw_fd(), buf, Some((&storage, len)), control, 0)
+    }
+
+    pub fn set_broadcast(&self, on: bool) -> io::Result<()> {
+        self.inner.set_broadcast(on)
+    }
+
+    pub fn broadcast(&self) -> io::Result<bool> {
+        self.inner.broadcast()
+    }
+
+    pub fn set_ttl(&self, ttl: u32) -> io::Result<()> {
+        self.inner.set_ttl(ttl)
+    }
+
+    pub fn ttl(&self) -> io::Result<u32> {
+        self.inner.ttl()
+    }
+
+    pub fn set_multicast_loop_v4(&self, on: bool) -> io::Result<()> {
+        self.inner.set_multicast_loop_v4(on)
+    }
+
+    pub fn multicast_loop_v4(&self) -> io::Result<bool> {
+        self.inner.multicast_loop_v4()
+    }
+
+    pub fn set_multicast_ttl_v4(&self, ttl: u32) -> io::Result<()> {
+        self.inner.set_multicast_ttl_v4(ttl)
+    }
+
+    pub fn multicast_ttl_v4(&self) -> io::Result<u32> {
+        self.inner.multicast_ttl_v4()
+    }
+
+    pub fn set_multicast_loop_v6(&self, on: bool) -> io::Result<()> {
+        self.inner.set_multicast_loop_v6(on)
+    }
+
+    pub fn multicast_loop_v6(&self) -> io::Result<bool> {
+        self.inner.multicast_loop_v6()
+    }
+
+    /// Join the multicast group `multiaddr` on the interface with the address
+    /// `interface`, `Ipv4Addr::UNSPECIFIED` lets the kernel choose.
+    pub fn join_multicast_v4(&self, multiaddr: &Ipv4Addr, interface: &Ipv4Addr) -> io::Result<()> {
+        self.inner.join_multicast_v4(multiaddr, interface)
+    }
+
+    /// Join the multicast group `multiaddr` on the interface with the index
+    /// `interface`, 0 lets the kernel choose.
+    pub fn join_multicast_v6(&self, multiaddr: &Ipv6Addr, interface: u32) -> io::Result<()> {
+        self.inner.join_multicast_v6(multiaddr, interface)
+    }
+
+    pub fn leave_multicast_v4(&self, multiaddr: &Ipv4Addr, interface: &Ipv4Addr) -> io::Result<()> {
+        self.inner.leave_multicast_v4(multiaddr, interface)
+    }
+
+    pub fn leave_multicast_v6(&self, multiaddr: &Ipv6Addr, interface: u32) -> io::Result<()> {
+        self.inner.leave_multicast_v6(multiaddr, interface)
+    }
+
+    pub fn take_error(&self) -> io::Result<Option<io::Error>> {
+        self.inner.take_error()
+    }
+
+    pub fn set_sockopt<O: SockOpt>(&self, opt: O, value: O::Value) -> io::Result<()> {
+        opt.set(self.as_raw_fd(), value)
+    }
+
+    pub fn sockopt<O: SockOpt>(&self, opt: O) -> io::Result<O::Value> {
+        opt.get(self.as_raw_fd())
+    }
+
+    pub fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        self.inner.set_nonblocking(nonblocking)
+    }
+}
+
+/// What `UdpSocket::recv_msg` received.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecvMeta {
+    /// The bytes written to the buffer.
+    pub len: usize,
+    /// The peer address.
+    pub addr: SocketAddr,
+    /// The datagram was longer than the buffer, the rest was discarded
+    /// (MSG_TRUNC).
+    pub truncated: bool,
+    /// Control messages were discarded for lack of room (MSG_CTRUNC),
+    /// `segment_size`, `pktinfo` or `timestamps` may be missing.
+    pub control_truncated: bool,
+    /// The size of the coalesced datagrams when GRO is enabled, `None` if a
+    /// single datagram was received.
+    pub segment_size: Option<usize>,
+    /// The local address and interface the datagram arrived on, with
+    /// `IpPktInfo` or `Ipv6RecvPktInfo` enabled.
+    pub pktinfo: Option<PktInfo>,
+    /// The kernel receive timestamps, with `TimestampNs` or `Timestamping`
+    /// enabled.
+    pub timestamps: Timestamps,
+}
+
+/// The local side of a datagram: the destination address of a received
+/// datagram, or the source address to send one from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PktInfo {
+    pub addr: IpAddr,
+    /// The interface index, 0 for any.
+    pub ifindex: u32,
+}
+
+impl RecvMeta {
+    /// Split the received bytes of `buf` back into datagrams.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use queen_io::net::UdpSocket;
+    /// use queen_io::sys::socket::UdpGro;
+    ///
+    /// let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    /// let addr = socket.local_addr().unwrap();
+    /// socket.set_sockopt(UdpGro, true).unwrap();
+    ///
+    /// socket.send_segmented(&[7; 3000], 1000, &addr).unwrap();
+    ///
+    /// let mut buf = [0; 65536];
+    /// let meta = socket.recv_msg(&mut buf).unwrap();
+    ///
+    /// let total: usize = meta.segments(&buf).map(|d| d.len()).sum();
+    /// assert!(meta.segments(&buf).all(|d| d.len() <= 1000));
+    /// assert_eq!(total, meta.len);
+    /// ```
+    pub fn segments<'a>(&self, buf: &'a [u8]) -> Chunks<'a, u8> {
+        let buf = &buf[..self.len];
+
+        buf.chunks(self.segment_size.unwrap_or(self.len).max(1))
+    }
+}
+
+/// A reusable set of buffers for `UdpSocket::recv_batch`.
+///
+/// # Example
+///
+/// ```
+/// use queen_io::net::UdpSocket;
+/// use queen_io::net::udp::{RecvBatch, SendBatch};
+///
+/// let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+/// let addr = socket.local_addr().unwrap();
+///
+/// let mut send_batch = SendBatch::with_capacity(32);
+/// socket.send_batch(&mut send_batch, &[(b"a", addr), (b"b", addr)]).unwrap();
+///
+/// let mut batch = RecvBatch::new(32, 1500);
+///
+/// while socket.recv_batch(&mut batch).is_ok() {
+///     for (buf, addr) in batch.iter() {
+///         println!("{:?} from {}", buf, addr);
+///     }
+/// }
+/// ```
+#[derive(Clone)]
+pub struct RecvBatch {
+    bufs: Vec<Box<[u8]>>,
+    lens: Vec<usize>,
+    truncated: Vec<bool>,
+    addrs: Vec<SocketAddr>,
+    storages: Vec<libc::sockaddr_storage>,
+    iovecs: Vec<libc::iovec>,
+    msgs: Vec<libc::mmsghdr>,
+    len: usize,
+}
+
+// the pointers in `iovecs` and `msgs` are only set and used by `recv_batch`
+unsafe impl Send for RecvBatch {}
+unsafe impl Sync for RecvBatch {}
+
+impl RecvBatch {
+    /// Create a batch of `count` buffers of `size` bytes each, longer
+    /// datagrams are truncated, see `truncated`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `count` or `size` is zero.
+    pub fn new(count: usize, size: usize) -> RecvBatch {
+        assert!(count > 0, "batch count must be non-zero");
+        assert!(size > 0, "batch buffer size must be non-zero");
+
+        RecvBatch {
+            bufs: vec![vec![0; size].into_boxed_slice(); count],
+            lens: vec![0; count],
+            truncated: vec![false; count],
+            addrs: vec![unspecified_addr(); count],
+            storages: vec![unsafe { mem::zeroed() }; count],
+            iovecs: vec![unsafe { mem::zeroed() }; count],
+            msgs: vec![unsafe { mem::zeroed() }; count],
+            len: 0,
+        }
+    }
+
+    /// The number of buffers.
+    pub fn capacity(&self) -> usize {
+        self.bufs.len()
+    }
+
+    /// The number of datagrams received by the last `recv_batch`.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The received datagram `index` and its peer address, the unspecified
+    /// address if the kernel reported an address which is not an inet one.
+    pub fn get(&self, index: usize) -> Option<(&[u8], SocketAddr)> {
+        if index < self.len {
+            Some((&self.bufs[index][..self.lens[index]], self.addrs[index]))
+        } else {
+            None
+        }
+    }
+
+    /// Whether the received datagram `index` was longer than its buffer
+    /// and was cut short.
+    pub fn truncated(&self, index: usize) -> bool {
+        index < self.len && self.truncated[index]
+    }
+
+    pub fn lens(&self) -> &[usize] {
+        &self.lens[..self.len]
+    }
+
+    pub fn addrs(&self) -> &[SocketAddr] {
+        &self.addrs[..self.len]
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&[u8], SocketAddr)> {
+        (0..self.len).map(move |i| (&self.bufs[i][..self.lens[i]], self.addrs[i]))
+    }
+}
+
+impl fmt::Debug for RecvBatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RecvBatch")
+            .field("capacity", &self.capacity())
+            .field("lens", &self.lens())
+            .field("addrs", &self.addrs())
+            .finish()
+    }
+}
+
+/// Reusable message headers for `UdpSocket::send_batch`.
+#[derive(Default)]
+pub struct SendBatch {
+    addrs: Vec<(libc::sockaddr_storage, libc::socklen_t)>,
+    iovecs: Vec<libc::iovec>,
+    hdrs: Vec<libc::mmsghdr>,
+}
+
+// the pointers in `iovecs` and `hdrs` are only set and used by `send_batch`
+unsafe impl Send for SendBatch {}
+unsafe impl Sync for SendBatch {}
+
+impl SendBatch {
+    pub fn new() -> SendBatch {
+        SendBatch::default()
+    }
+
+    /// Create a batch sending up to `count` datagrams without allocating.
+    pub fn with_capacity(count: usize) -> SendBatch {
+        SendBatch {
+            addrs: Vec::with_capacity(count),
+            iovecs: Vec::with_capacity(count),
+            hdrs: Vec::with_capacity(count),
+        }
+    }
+}
+
+impl fmt::Debug for SendBatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SendBatch")
+            .field("capacity", &self.hdrs.capacity())
+            .finish()
+    }
+}
+
+fn unspecified_addr() -> SocketAddr {
+    SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0))
+}
+
+impl Source for UdpSocket {
+    fn add(&self, epoll: &Epoll, token: Token, interest: Ready, opts: EpollOpt) -> io::Result<()> {
+        self.selector_id.associate_selector(epoll)?;
+        epoll.add(&self.as_raw_fd(), token, interest, opts)
+    }
+
+    fn modify(
+        &self,
+        epoll: &Epoll,
+        token: Token,
+        interest: Ready,
+        opts: EpollOpt,
+    ) -> io::Result<()> {
+        epoll.modify(&self.as_raw_fd(), token, interest, opts)
+    }
+
+    fn delete(&self, epoll: &Epoll) -> io::Result<()> {
+        epoll.delete(&self.as_raw_fd())
+    }
+}
+
+impl FromRawFd for UdpSocket {
+    unsafe fn from_raw_fd(fd: RawFd) -> UdpSocket {
+        UdpSocket {
+            inner: net::UdpSocket::from_raw_fd(fd),
+            selector_id: SelectorId::new(),
+        }
+    }
+}
+
+impl IntoRawFd for UdpSocket {
+    fn into_raw_fd(self) -> RawFd {
+        self.inner.into_raw_fd()
+    }
+}
+
+impl AsRawFd for UdpSocket {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::ErrorKind;
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::time::Duration;
+
+    use crate::epoll::{Epoll, EpollOpt, Events, Ready, Token};
+
+    use crate::sys::socket::{IpPktInfo, UdpGro, UdpSegment};
+
+    use super::{PktInfo, RecvBatch, SendBatch, UdpSocket};
+
+    #[test]
+    fn send_recv() {
+        let socket1 = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let socket2 = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr1 = socket1.local_addr().unwrap();
+        let addr2 = socket2.local_addr().unwrap();
+
+        let epoll = Epoll::new().unwrap();
+        let mut events = Events::with_capacity(8);
+        epoll
+            .add(&socket2, Token(2), Ready::readable(), EpollOpt::edge())
+            .unwrap();
+
+        let mut buf = [0; 16];
+        let err = socket2.recv_from(&mut buf).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::WouldBlock);
+
+        socket1.send_to(b"hello", addr2).unwrap();
+
+        epoll
+            .wait(&mut events, Some(Duration::from_secs(5)))
+            .unwrap();
+        assert_eq!(events.len(), 1);
+
+        assert_eq!(socket2.peek_from(&mut buf).unwrap(), (5, addr1));
+        assert_eq!(socket2.recv_from(&mut buf).unwrap(), (5, addr1));
+        assert_eq!(&buf[..5], b"hello");
+
+        socket2.connect(addr1).unwrap();
+        socket2.send(b"world").unwrap();
+
+        epoll
+            .add(&socket1, Token(1), Ready::readable(), EpollOpt::edge())
+            .unwrap();
+        epoll
+            .wait(&mut events, Some(Duration::from_secs(5)))
+            .unwrap();
+
+        assert_eq!(socket1.recv(&mut buf).unwrap(), 5);
+        assert_eq!(&buf[..5], b"world");
+    }
+
+    #[test]
+    fn multicast_v4() {
+        let socket = UdpSocket::bind("0.0.0.0:0").unwrap();
+        let group = Ipv4Addr::new(239, 255, 42, 99);
+
+        socket.set_multicast_ttl_v4(4).unwrap();
+        assert_eq!(socket.multicast_ttl_v4().unwrap(), 4);
+        socket.set_multicast_loop_v4(false).unwrap();
+        assert!(!socket.multicast_loop_v4().unwrap());
+        socket.set_broadcast(true).unwrap();
+        assert!(socket.broadcast().unwrap());
+
+        // there may be no multicast capable interface in the sandbox
+        if socket
+            .join_multicast_v4(&group, &Ipv4Addr::UNSPECIFIED)
+            .is_ok()
+        {
+            socket
+                .leave_multicast_v4(&group, &Ipv4Addr::UNSPECIFIED)
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn batch() {
+        let socket1 = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let socket2 = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr1 = socket1.local_addr().unwrap();
+        let addr2 = socket2.local_addr().unwrap();
+
+        let epoll = Epoll::new().unwrap();
+        let mut events = Events::with_capacity(8);
+        epoll
+            .add(&socket2, Token(2), Ready::readable(), EpollOpt::edge())
+            .unwrap();
+
+        let msgs: Vec<_> = (0..5u8).map(|i| (vec![i; i as usize + 1], addr2)).collect();
+        let mut send_batch = SendBatch::new();
+        assert_eq!(socket1.send_batch(&mut send_batch, &msgs).unwrap(), 5);
+
+        epoll
+            .wait(&mut events, Some(Duration::from_secs(5)))
+            .unwrap();
+        assert_eq!(events.len(), 1);
+
+        let mut batch = RecvBatch::new(4, 4);
+
+        assert_eq!(socket2.recv_batch(&mut batch).unwrap(), 4);
+        assert_eq!(batch.lens(), &[1, 2, 3, 4]);
+        assert_eq!(batch.get(2), Some((&[2, 2, 2][..], addr1)));
+        assert!(!batch.truncated(3));
+
+        assert_eq!(socket2.recv_batch(&mut batch).unwrap(), 1);
+        assert_eq!(batch.get(0), Some((&[4, 4, 4, 4][..], addr1)));
+        assert!(batch.truncated(0));
+        assert_eq!(batch.get(1), None);
+
+        let err = socket2.recv_batch(&mut batch).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::WouldBlock);
+        assert!(batch.is_empty());
+    }
+
+    #[test]
+    #[should_panic]
+    fn empty_batch() {
+        RecvBatch::new(0, 1500);
+    }
+
+    #[test]
+    fn segmentation_offload() {
+        let socket1 = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let socket2 = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr2 = socket2.local_addr().unwrap();
+
+        socket2.set_sockopt(UdpGro, true).unwrap();
+        assert!(socket2.sockopt(UdpGro).unwrap());
+        socket1.set_sockopt(UdpSegment, 1200).unwrap();
+        assert_eq!(socket1.sockopt(UdpSegment).unwrap(), 1200);
+
+        let data: Vec<u8> = (0..2500).map(|i| i as u8).collect();
+        assert_eq!(socket1.send_segmented(&data, 1000, &addr2).unwrap(), 2500);
+
+        let mut buf = [0; 65536];
+        let mut received = Vec::new();
+
+        while received.len() < 3 {
+            let meta = socket2.recv_msg(&mut buf).unwrap();
+            received.extend(meta.segments(&buf).map(|d| d.to_vec()));
+        }
+
+        assert_eq!(received.len(), 3);
+        assert_eq!(received[0], &data[..1000]);
+        assert_eq!(received[2], &data[2000..]);
+    }
+
+    #[test]
+    fn pktinfo() {
+        let server = UdpSocket::bind("0.0.0.0:0").unwrap();
+        let port = server.local_addr().unwrap().port();
+        server.set_sockopt(IpPktInfo, true).unwrap();
+
+        let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+        client.send_to(b"ping", ("127.0.0.1", port)).unwrap();
+
+        let epoll = Epoll::new().unwrap();
+        let mut events = Events::with_capacity(8);
+        epoll
+            .add(&server, Token(0), Ready::readable(), EpollOpt::level())
+            .unwrap();
+        epoll
+            .wait(&mut events, Some(Duration::from_secs(5)))
+            .unwrap();
+
+        let mut buf = [0; 16];
+        let meta = server.recv_msg(&mut buf).unwrap();
+        let lo = unsafe { libc::if_nametoindex(c"lo".as_ptr()) };
+
+        assert_eq!(meta.addr, client.local_addr().unwrap());
+        assert!(!meta.truncated && !meta.control_truncated);
+        assert_eq!(
+            meta.pktinfo,
+            Some(PktInfo {
+                addr: IpAddr::V4(Ipv4Addr::LOCALHOST),
+                ifindex: lo
+            })
+        );
+
+        server
+            .send_msg(b"pong", &meta.addr, &meta.pktinfo.unwrap())
+            .unwrap();
+
+        epoll
+            .add(&client, Token(1), Ready::readable(), EpollOpt::level())
+            .unwrap();
+        epoll
+            .wait(&mut events, Some(Duration::from_secs(5)))
+            .unwrap();
+
+        let (n, from) = client.recv_from(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"pong");
+        assert_eq!(from, ([127, 0, 0, 1], port).into());
+
+        client.send_to(b"truncated", ("127.0.0.1", port)).unwrap();
+        epoll
+            .wait(&mut events, Some(Duration::from_secs(5)))
+            .unwrap();
+
+        let meta = server.recv_msg(&mut buf[..4]).unwrap();
+        assert_eq!(meta.len, 4);
+        assert!(meta.truncated);
+    }
+
+    #[test]
+    #[cfg(so_timestamping)]
+    fn timestamps() {
+        use std::time::SystemTime;
+
+        use crate::sys::socket::TimestampNs;
+
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = socket.local_addr().unwrap();
+        socket.set_sockopt(TimestampNs, true).unwrap();
+        assert!(socket.sockopt(TimestampNs).unwrap());
+
+        let before = SystemTime::now();
+        socket.send_to(b"tick", addr).unwrap();
+
+        let mut buf = [0; 16];
+        let meta = socket.recv_msg(&mut buf).unwrap();
+
+        let received = meta.timestamps.software.unwrap();
+        assert!(received >= before - Duration::from_millis(10));
+        assert!(received <= SystemTime::now());
+        assert_eq!(meta.timestamps.hardware, None);
+    }
+}
@@ -1,12 +1,24 @@
+use std::fmt;
+use std::fs;
 use std::io;
 use std::io::{Read, Write};
+use std::mem;
 use std::net::Shutdown;
-use std::os::unix::io::{AsRawFd, FromRawFd, IntoRawFd, RawFd};
+use std::os::unix::fs::{FileTypeExt, MetadataExt, PermissionsExt};
+use std::os::unix::io::{AsRawFd, FromRawFd, IntoRawFd, OwnedFd, RawFd};
 use std::os::unix::net::{self, SocketAddr};
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use std::time::Duration;
 
 use crate::epoll::{Epoll, EpollOpt, Ready, SelectorId, Source, Token};
+use crate::sys::cmsg::{self, CmsgBuf};
+use crate::sys::socket::{self, SockOpt};
+
+/// The most file descriptors passed in one message, SCM_MAX_FD of the
+/// kernel.
+pub const MAX_FDS: usize = 253;
 
 #[derive(Debug)]
 pub struct UnixStream {
@@ -18,13 +30,125 @@ pub struct UnixStream {
 pub struct UnixListener {
     inner: net::UnixListener,
     selector_id: SelectorId,
+    filter: Option<CredFilter>,
+    unlink: Option<UnlinkOnDrop>,
+}
+
+/// The credentials of a unix socket peer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct UCred {
+    pub pid: libc::pid_t,
+    pub uid: libc::uid_t,
+    pub gid: libc::gid_t,
+}
+
+impl UCred {
+    /// The credentials of this process.
+    pub fn current() -> UCred {
+        unsafe {
+            UCred {
+                pid: libc::getpid(),
+                uid: libc::getuid(),
+                gid: libc::getgid(),
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+struct CredFilter(Arc<dyn Fn(&UCred) -> bool + Send + Sync>);
+
+impl fmt::Debug for CredFilter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("CredFilter")
+    }
+}
+
+#[derive(Debug)]
+pub struct UnixDatagram {
+    inner: net::UnixDatagram,
+    selector_id: SelectorId,
+}
+
+/// A connected SOCK_SEQPACKET socket: reliable and ordered like a stream,
+/// with the message boundaries of datagrams.
+#[derive(Debug)]
+pub struct UnixSeqpacket {
+    inner: OwnedFd,
+    selector_id: SelectorId,
+}
+
+/// A SOCK_SEQPACKET socket listening for `UnixSeqpacket` connections.
+///
+/// # Example
+///
+/// ```
+/// use queen_io::net::unix::{UnixSeqpacket, UnixSeqpacketListener};
+///
+/// let path = std::env::temp_dir().join(format!("queen-io-doc-{}.sock", std::process::id()));
+/// let _ = std::fs::remove_file(&path);
+///
+/// let listener = UnixSeqpacketListener::bind(&path).unwrap();
+/// let client = UnixSeqpacket::connect(&path).unwrap();
+/// let (server, _) = listener.accept().unwrap();
+///
+/// client.send(b"hello").unwrap();
+/// client.send(b"world").unwrap();
+///
+/// let mut buf = [0; 64];
+/// assert_eq!(server.recv(&mut buf).unwrap(), 5);
+/// assert_eq!(&buf[..5], b"hello");
+///
+/// std::fs::remove_file(&path).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct UnixSeqpacketListener {
+    inner: OwnedFd,
+    selector_id: SelectorId,
 }
 
 impl UnixStream {
+    /// Connect to the socket at `path` without blocking, see `connect_addr`.
     pub fn connect<P: AsRef<Path>>(path: P) -> io::Result<UnixStream> {
-        let stream = net::UnixStream::connect(path)?;
+        UnixStream::connect_addr(&SocketAddr::from_pathname(path)?)
+    }
 
-        UnixStream::new(stream)
+    /// Connect to `addr`, a path or an abstract name, without blocking.
+    ///
+    /// A unix connect completes at once, the stream is writable when it is
+    /// returned. `WouldBlock` is returned if the backlog of the listener is
+    /// full, the connect has to be retried later. If the kernel reports
+    /// EINPROGRESS, wait for `Ready::writable()` and check `take_error`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::os::linux::net::SocketAddrExt;
+    /// use std::os::unix::net::SocketAddr;
+    ///
+    /// use queen_io::net::unix::{UnixListener, UnixStream};
+    ///
+    /// let name = format!("queen-io-doc-{}", std::process::id());
+    /// let addr = SocketAddr::from_abstract_name(name).unwrap();
+    ///
+    /// let listener = UnixListener::bind_addr(&addr).unwrap();
+    /// let stream = UnixStream::connect_addr(&addr).unwrap();
+    ///
+    /// assert!(listener.accept().is_ok());
+    /// ```
+    pub fn connect_addr(addr: &SocketAddr) -> io::Result<UnixStream> {
+        let fd = socket::socket(libc::AF_UNIX, libc::SOCK_STREAM, 0)?;
+        let stream = unsafe { UnixStream::from_raw_fd(fd) };
+
+        let (storage, len) = socket::unix_socket_addr(addr);
+
+        match syscall!(connect(fd, &storage as *const _ as *const libc::sockaddr, len)) {
+            Ok(_) => (),
+            Err(ref e) if e.raw_os_error() == Some(libc::EINPROGRESS) => (),
+            Err(e) => return Err(e),
+        }
+
+        Ok(stream)
     }
 
     pub fn new(stream: net::UnixStream) -> io::Result<UnixStream> {
@@ -81,9 +205,49 @@ impl UnixStream {
         self.inner.take_error()
     }
 
+    pub fn set_sockopt<O: SockOpt>(&self, opt: O, value: O::Value) -> io::Result<()> {
+        opt.set(self.as_raw_fd(), value)
+    }
+
+    pub fn sockopt<O: SockOpt>(&self, opt: O) -> io::Result<O::Value> {
+        opt.get(self.as_raw_fd())
+    }
+
     pub fn shutdown(&self, how: Shutdown) -> io::Result<()> {
         self.inner.shutdown(how)
     }
+
+    /// Send `buf` along with the file descriptors `fds` (SCM_RIGHTS), at most
+    /// `MAX_FDS`. The descriptors stay open in this process.
+    pub fn send_with_fds(&self, buf: &[u8], fds: &[RawFd]) -> io::Result<usize> {
+        send_with_fds(self.as_raw_fd(), buf, fds)
+    }
+
+    /// Receive into `buf` and append the file descriptors passed along to
+    /// `fds`, they are close-on-exec.
+    ///
+    /// An `InvalidData` error is returned if the kernel truncated the
+    /// control messages, the descriptors which fit are still appended.
+    pub fn recv_with_fds(&self, buf: &mut [u8], fds: &mut Vec<OwnedFd>) -> io::Result<usize> {
+        recv_with_fds(self.as_raw_fd(), buf, fds)
+    }
+
+    /// The credentials of the peer when it connected, SO_PEERCRED.
+    pub fn peer_cred(&self) -> io::Result<UCred> {
+        peer_cred(self.as_raw_fd())
+    }
+
+    /// Send `buf` with the credentials of this process (SCM_CREDENTIALS),
+    /// the kernel checks them.
+    pub fn send_with_cred(&self, buf: &[u8]) -> io::Result<usize> {
+        send_with_cred(self.as_raw_fd(), buf)
+    }
+
+    /// Receive into `buf` along with the credentials of the sender, which
+    /// are only reported when `PassCred` is enabled on this socket.
+    pub fn recv_with_cred(&self, buf: &mut [u8]) -> io::Result<(usize, Option<UCred>)> {
+        recv_with_cred(self.as_raw_fd(), buf)
+    }
 }
 
 impl Read for UnixStream {
@@ -92,7 +256,7 @@ impl Read for UnixStream {
     }
 }
 
-impl<'a> Read for &'a UnixStream {
+impl Read for &UnixStream {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
         (&self.inner).read(buf)
     }
@@ -108,7 +272,7 @@ impl Write for UnixStream {
     }
 }
 
-impl<'a> Write for &'a UnixStream {
+impl Write for &UnixStream {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
         (&self.inner).write(buf)
     }
@@ -160,11 +324,187 @@ impl AsRawFd for UnixStream {
     }
 }
 
+/// Configures a `UnixListener` and the socket file it is bound to.
+///
+/// The socket is created non-blocking and close-on-exec. The file options
+/// are ignored for abstract addresses.
+///
+/// # Example
+///
+/// ```
+/// use queen_io::net::unix::UnixListenerBuilder;
+///
+/// let path = std::env::temp_dir().join(format!("queen-io-doc-{}.sock", std::process::id()));
+///
+/// let listener = UnixListenerBuilder::new()
+///     .mode(0o660)
+///     .remove_stale(true)
+///     .unlink_on_drop(true)
+///     .bind(&path)
+///     .unwrap();
+///
+/// drop(listener);
+/// assert!(!path.exists());
+/// ```
+#[derive(Debug, Clone)]
+pub struct UnixListenerBuilder {
+    mode: Option<u32>,
+    remove_stale: bool,
+    unlink_on_drop: bool,
+    backlog: i32,
+}
+
+impl UnixListenerBuilder {
+    pub fn new() -> UnixListenerBuilder {
+        UnixListenerBuilder {
+            mode: None,
+            remove_stale: false,
+            unlink_on_drop: false,
+            backlog: 128,
+        }
+    }
+
+    /// The permissions of the socket file, set before the socket listens.
+    /// By default they follow the umask.
+    pub fn mode(&mut self, mode: u32) -> &mut UnixListenerBuilder {
+        self.mode = Some(mode);
+        self
+    }
+
+    /// Remove the socket file left by a listener which is gone. The file is
+    /// only removed if it is a socket that refuses connections.
+    pub fn remove_stale(&mut self, remove: bool) -> &mut UnixListenerBuilder {
+        self.remove_stale = remove;
+        self
+    }
+
+    /// Remove the socket file when the listener is dropped, unless it was
+    /// replaced in the meantime.
+    pub fn unlink_on_drop(&mut self, unlink: bool) -> &mut UnixListenerBuilder {
+        self.unlink_on_drop = unlink;
+        self
+    }
+
+    /// The backlog passed to listen(2), 128 by default.
+    pub fn backlog(&mut self, backlog: i32) -> &mut UnixListenerBuilder {
+        self.backlog = backlog;
+        self
+    }
+
+    pub fn bind<P: AsRef<Path>>(&self, path: P) -> io::Result<UnixListener> {
+        self.bind_addr(&SocketAddr::from_pathname(path)?)
+    }
+
+    pub fn bind_addr(&self, addr: &SocketAddr) -> io::Result<UnixListener> {
+        let path = addr.as_pathname();
+
+        if let (Some(path), true) = (path, self.remove_stale) {
+            remove_stale(path)?;
+        }
+
+        let fd = socket::socket(libc::AF_UNIX, libc::SOCK_STREAM, 0)?;
+        let mut listener = unsafe { UnixListener::from_raw_fd(fd) };
+
+        let (storage, len) = socket::unix_socket_addr(addr);
+        syscall!(bind(fd, &storage as *const _ as *const libc::sockaddr, len))?;
+
+        if let Some(path) = path {
+            if self.unlink_on_drop {
+                listener.unlink = Some(UnlinkOnDrop::new(path)?);
+            }
+
+            if let Some(mode) = self.mode {
+                fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+            }
+        }
+
+        syscall!(listen(fd, self.backlog))?;
+
+        Ok(listener)
+    }
+}
+
+impl Default for UnixListenerBuilder {
+    fn default() -> UnixListenerBuilder {
+        UnixListenerBuilder::new()
+    }
+}
+
+fn remove_stale(path: &Path) -> io::Result<()> {
+    match fs::symlink_metadata(path) {
+        Ok(meta) if meta.file_type().is_socket() => (),
+        // bind reports the path in use
+        Ok(_) => return Ok(()),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    }
+
+    match UnixStream::connect_addr(&SocketAddr::from_pathname(path)?) {
+        Err(ref e) if e.kind() == io::ErrorKind::ConnectionRefused => (),
+        // a listener is alive, bind reports the path in use
+        _ => return Ok(()),
+    }
+
+    match fs::remove_file(path) {
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        other => other,
+    }
+}
+
+/// Removes the socket file of a listener, if it is still the one the
+/// listener was bound to.
+#[derive(Debug)]
+struct UnlinkOnDrop {
+    path: PathBuf,
+    dev: u64,
+    ino: u64,
+    armed: AtomicBool,
+}
+
+impl UnlinkOnDrop {
+    fn new(path: &Path) -> io::Result<UnlinkOnDrop> {
+        let meta = fs::symlink_metadata(path)?;
+
+        Ok(UnlinkOnDrop {
+            path: path.to_owned(),
+            dev: meta.dev(),
+            ino: meta.ino(),
+            armed: AtomicBool::new(true),
+        })
+    }
+
+    fn disarm(&self) {
+        self.armed.store(false, Ordering::Relaxed);
+    }
+}
+
+impl Drop for UnlinkOnDrop {
+    fn drop(&mut self) {
+        if !self.armed.load(Ordering::Relaxed) {
+            return;
+        }
+
+        if let Ok(meta) = fs::symlink_metadata(&self.path) {
+            if meta.dev() == self.dev && meta.ino() == self.ino {
+                let _ = fs::remove_file(&self.path);
+            }
+        }
+    }
+}
+
 impl UnixListener {
+    pub fn builder() -> UnixListenerBuilder {
+        UnixListenerBuilder::new()
+    }
+
+    /// Bind to `path`, the socket is created non-blocking.
     pub fn bind<P: AsRef<Path>>(path: P) -> io::Result<UnixListener> {
-        let listener = net::UnixListener::bind(path)?;
+        UnixListenerBuilder::new().bind(path)
+    }
 
-        UnixListener::new(listener)
+    /// Bind to `addr`, a path or an abstract name.
+    pub fn bind_addr(addr: &SocketAddr) -> io::Result<UnixListener> {
+        UnixListenerBuilder::new().bind_addr(addr)
     }
 
     pub fn new(sock: net::UnixListener) -> io::Result<UnixListener> {
@@ -173,19 +513,77 @@ impl UnixListener {
         Ok(UnixListener {
             inner: sock,
             selector_id: SelectorId::new(),
+            filter: None,
+            unlink: None,
         })
     }
 
+    /// Only hand out connections whose peer credentials pass `filter`, the
+    /// others are closed by `accept` right away.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use queen_io::net::unix::UnixListener;
+    ///
+    /// let mut listener = UnixListener::bind("/run/admin.sock").unwrap();
+    /// listener.set_cred_filter(|cred| cred.uid == 0);
+    /// ```
+    pub fn set_cred_filter<F>(&mut self, filter: F)
+    where
+        F: Fn(&UCred) -> bool + Send + Sync + 'static,
+    {
+        self.filter = Some(CredFilter(Arc::new(filter)));
+    }
+
+    pub fn clear_cred_filter(&mut self) {
+        self.filter = None;
+    }
+
+    /// Accept a connection with accept4(2), the stream is non-blocking and
+    /// close-on-exec without further syscalls.
+    ///
+    /// With a credentials filter, rejected connections and connections whose
+    /// credentials cannot be read are closed and the next one is accepted.
     pub fn accept(&self) -> io::Result<(UnixStream, SocketAddr)> {
-        self.inner
-            .accept()
-            .and_then(|(s, a)| Ok((UnixStream::new(s)?, a)))
+        loop {
+            let mut storage: libc::sockaddr_un = unsafe { mem::zeroed() };
+            let (fd, len) = socket::accept4(self.as_raw_fd(), &mut storage)?;
+
+            let stream = unsafe { UnixStream::from_raw_fd(fd) };
+
+            if let Some(CredFilter(ref filter)) = self.filter {
+                match stream.peer_cred() {
+                    Ok(cred) if filter(&cred) => (),
+                    _ => continue,
+                }
+            }
+
+            let addr = socket::to_unix_addr(&storage, len)?;
+
+            return Ok((stream, addr));
+        }
+    }
+
+    /// Accept up to `max` connections, like `TcpListener::accept_batch`.
+    pub fn accept_batch(&self, max: usize) -> io::Result<Vec<(UnixStream, SocketAddr)>> {
+        socket::accept_batch(max, || self.accept())
+    }
+
+    /// Do not remove the socket file on drop, e.g. once the listener was
+    /// handed to another process.
+    pub fn keep_socket_file(&self) {
+        if let Some(ref unlink) = self.unlink {
+            unlink.disarm();
+        }
     }
 
     pub fn try_clone(&self) -> io::Result<UnixListener> {
         self.inner.try_clone().map(|s| UnixListener {
             inner: s,
             selector_id: self.selector_id.clone(),
+            filter: self.filter.clone(),
+            unlink: None,
         })
     }
 
@@ -200,6 +598,14 @@ impl UnixListener {
     pub fn take_error(&self) -> io::Result<Option<io::Error>> {
         self.inner.take_error()
     }
+
+    pub fn set_sockopt<O: SockOpt>(&self, opt: O, value: O::Value) -> io::Result<()> {
+        opt.set(self.as_raw_fd(), value)
+    }
+
+    pub fn sockopt<O: SockOpt>(&self, opt: O) -> io::Result<O::Value> {
+        opt.get(self.as_raw_fd())
+    }
 }
 
 impl Source for UnixListener {
@@ -228,12 +634,17 @@ impl FromRawFd for UnixListener {
         UnixListener {
             inner: net::UnixListener::from_raw_fd(fd),
             selector_id: SelectorId::new(),
+            filter: None,
+            unlink: None,
         }
     }
 }
 
 impl IntoRawFd for UnixListener {
+    /// The socket file is kept, the fd may be handed to another process.
     fn into_raw_fd(self) -> RawFd {
+        self.keep_socket_file();
+
         self.inner.into_raw_fd()
     }
 }
@@ -243,3 +654,853 @@ impl AsRawFd for UnixListener {
         self.inner.as_raw_fd()
     }
 }
+
+impl UnixDatagram {
+    pub fn bind<P: AsRef<Path>>(path: P) -> io::Result<UnixDatagram> {
+        let socket = net::UnixDatagram::bind(path)?;
+
+        UnixDatagram::new(socket)
+    }
+
+    /// Bind to `addr`, a path or an abstract name.
+    pub fn bind_addr(addr: &SocketAddr) -> io::Result<UnixDatagram> {
+        let socket = net::UnixDatagram::bind_addr(addr)?;
+
+        UnixDatagram::new(socket)
+    }
+
+    /// Create a socket which is not bound to any address.
+    pub fn unbound() -> io::Result<UnixDatagram> {
+        let socket = net::UnixDatagram::unbound()?;
+
+        UnixDatagram::new(socket)
+    }
+
+    pub fn pair() -> io::Result<(UnixDatagram, UnixDatagram)> {
+        let (socket1, socket2) = net::UnixDatagram::pair()?;
+
+        Ok((UnixDatagram::new(socket1)?, UnixDatagram::new(socket2)?))
+    }
+
+    pub fn new(socket: net::UnixDatagram) -> io::Result<UnixDatagram> {
+        socket.set_nonblocking(true)?;
+
+        Ok(UnixDatagram {
+            inner: socket,
+            selector_id: SelectorId::new(),
+        })
+    }
+
+    /// Set the default destination of `send` and only receive datagrams
+    /// from `path`.
+    pub fn connect<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        self.inner.connect(path)
+    }
+
+    pub fn connect_addr(&self, addr: &SocketAddr) -> io::Result<()> {
+        self.inner.connect_addr(addr)
+    }
+
+    pub fn try_clone(&self) -> io::Result<UnixDatagram> {
+        self.inner.try_clone().map(|s| UnixDatagram {
+            inner: s,
+            selector_id: self.selector_id.clone(),
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.peer_addr()
+    }
+
+    pub fn send_to<P: AsRef<Path>>(&self, buf: &[u8], path: P) -> io::Result<usize> {
+        self.inner.send_to(buf, path)
+    }
+
+    pub fn send_to_addr(&self, buf: &[u8], addr: &SocketAddr) -> io::Result<usize> {
+        self.inner.send_to_addr(buf, addr)
+    }
+
+    pub fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        self.inner.recv_from(buf)
+    }
+
+    pub fn send(&self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.send(buf)
+    }
+
+    pub fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.recv(buf)
+    }
+
+    pub fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        self.inner.set_nonblocking(nonblocking)
+    }
+
+    pub fn take_error(&self) -> io::Result<Option<io::Error>> {
+        self.inner.take_error()
+    }
+
+    pub fn set_sockopt<O: SockOpt>(&self, opt: O, value: O::Value) -> io::Result<()> {
+        opt.set(self.as_raw_fd(), value)
+    }
+
+    pub fn sockopt<O: SockOpt>(&self, opt: O) -> io::Result<O::Value> {
+        opt.get(self.as_raw_fd())
+    }
+
+    pub fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        self.inner.shutdown(how)
+    }
+
+    /// Send `buf` to the connected peer along with the file descriptors
+    /// `fds`, like `UnixStream::send_with_fds`.
+    pub fn send_with_fds(&self, buf: &[u8], fds: &[RawFd]) -> io::Result<usize> {
+        send_with_fds(self.as_raw_fd(), buf, fds)
+    }
+
+    /// Receive a datagram and the file descriptors passed along, like
+    /// `UnixStream::recv_with_fds`.
+    pub fn recv_with_fds(&self, buf: &mut [u8], fds: &mut Vec<OwnedFd>) -> io::Result<usize> {
+        recv_with_fds(self.as_raw_fd(), buf, fds)
+    }
+
+    /// Send a datagram with the credentials of this process, like
+    /// `UnixStream::send_with_cred`.
+    pub fn send_with_cred(&self, buf: &[u8]) -> io::Result<usize> {
+        send_with_cred(self.as_raw_fd(), buf)
+    }
+
+    /// Receive a datagram and the credentials of the sender, like
+    /// `UnixStream::recv_with_cred`.
+    pub fn recv_with_cred(&self, buf: &mut [u8]) -> io::Result<(usize, Option<UCred>)> {
+        recv_with_cred(self.as_raw_fd(), buf)
+    }
+}
+
+impl Source for UnixDatagram {
+    fn add(&self, epoll: &Epoll, token: Token, interest: Ready, opts: EpollOpt) -> io::Result<()> {
+        self.selector_id.associate_selector(epoll)?;
+        epoll.add(&self.as_raw_fd(), token, interest, opts)
+    }
+
+    fn modify(
+        &self,
+        epoll: &Epoll,
+        token: Token,
+        interest: Ready,
+        opts: EpollOpt,
+    ) -> io::Result<()> {
+        epoll.modify(&self.as_raw_fd(), token, interest, opts)
+    }
+
+    fn delete(&self, epoll: &Epoll) -> io::Result<()> {
+        epoll.delete(&self.as_raw_fd())
+    }
+}
+
+impl FromRawFd for UnixDatagram {
+    unsafe fn from_raw_fd(fd: RawFd) -> UnixDatagram {
+        UnixDatagram {
+            inner: net::UnixDatagram::from_raw_fd(fd),
+            selector_id: SelectorId::new(),
+        }
+    }
+}
+
+impl IntoRawFd for UnixDatagram {
+    fn into_raw_fd(self) -> RawFd {
+        self.inner.into_raw_fd()
+    }
+}
+
+impl AsRawFd for UnixDatagram {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+impl UnixSeqpacket {
+    /// Connect to the `UnixSeqpacketListener` bound to `path`.
+    pub fn connect<P: AsRef<Path>>(path: P) -> io::Result<UnixSeqpacket> {
+        UnixSeqpacket::connect_addr(&SocketAddr::from_pathname(path)?)
+    }
+
+    /// Connect to `addr`, a path or an abstract name, without blocking, like
+    /// `UnixStream::connect_addr`.
+    pub fn connect_addr(addr: &SocketAddr) -> io::Result<UnixSeqpacket> {
+        let socket = seqpacket_socket()?;
+        let (storage, len) = socket::unix_socket_addr(addr);
+
+        match syscall!(connect(
+            socket.as_raw_fd(),
+            &storage as *const _ as *const libc::sockaddr,
+            len
+        )) {
+            Ok(_) => (),
+            Err(ref e) if e.raw_os_error() == Some(libc::EINPROGRESS) => (),
+            Err(e) => return Err(e),
+        }
+
+        Ok(UnixSeqpacket::from_fd(socket))
+    }
+
+    pub fn pair() -> io::Result<(UnixSeqpacket, UnixSeqpacket)> {
+        let mut fds = [0; 2];
+        let ty = libc::SOCK_SEQPACKET | libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC;
+
+        syscall!(socketpair(libc::AF_UNIX, ty, 0, fds.as_mut_ptr()))?;
+
+        unsafe {
+            Ok((
+                UnixSeqpacket::from_raw_fd(fds[0]),
+                UnixSeqpacket::from_raw_fd(fds[1]),
+            ))
+        }
+    }
+
+    fn from_fd(fd: OwnedFd) -> UnixSeqpacket {
+        UnixSeqpacket {
+            inner: fd,
+            selector_id: SelectorId::new(),
+        }
+    }
+
+    pub fn try_clone(&self) -> io::Result<UnixSeqpacket> {
+        self.inner.try_clone().map(|fd| UnixSeqpacket {
+            inner: fd,
+            selector_id: self.selector_id.clone(),
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        unix_name(self.as_raw_fd(), libc::getsockname)
+    }
+
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        unix_name(self.as_raw_fd(), libc::getpeername)
+    }
+
+    /// The credentials of the peer when it connected, SO_PEERCRED.
+    pub fn peer_cred(&self) -> io::Result<UCred> {
+        peer_cred(self.as_raw_fd())
+    }
+
+    /// Send `buf` as one message.
+    pub fn send(&self, buf: &[u8]) -> io::Result<usize> {
+        let n = syscall!(send(
+            self.as_raw_fd(),
+            buf.as_ptr() as *const libc::c_void,
+            buf.len(),
+            libc::MSG_NOSIGNAL
+        ))?;
+
+        Ok(n as usize)
+    }
+
+    /// Receive one message, the part which does not fit in `buf` is
+    /// discarded. Returns 0 when the peer has closed the connection.
+    pub fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        self.recv_flags(buf, 0)
+    }
+
+    /// Receive the next message without removing it from the queue.
+    pub fn peek(&self, buf: &mut [u8]) -> io::Result<usize> {
+        self.recv_flags(buf, libc::MSG_PEEK)
+    }
+
+    fn recv_flags(&self, buf: &mut [u8], flags: libc::c_int) -> io::Result<usize> {
+        let n = syscall!(recv(
+            self.as_raw_fd(),
+            buf.as_mut_ptr() as *mut libc::c_void,
+            buf.len(),
+            flags
+        ))?;
+
+        Ok(n as usize)
+    }
+
+    pub fn take_error(&self) -> io::Result<Option<io::Error>> {
+        take_error(self.as_raw_fd())
+    }
+
+    pub fn set_sockopt<O: SockOpt>(&self, opt: O, value: O::Value) -> io::Result<()> {
+        opt.set(self.as_raw_fd(), value)
+    }
+
+    pub fn sockopt<O: SockOpt>(&self, opt: O) -> io::Result<O::Value> {
+        opt.get(self.as_raw_fd())
+    }
+
+    pub fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        let how = match how {
+            Shutdown::Read => libc::SHUT_RD,
+            Shutdown::Write => libc::SHUT_WR,
+            Shutdown::Both => libc::SHUT_RDWR,
+        };
+
+        syscall!(shutdown(self.as_raw_fd(), how))?;
+
+        Ok(())
+    }
+}
+
+impl Source for UnixSeqpacket {
+    fn add(&self, epoll: &Epoll, token: Token, interest: Ready, opts: EpollOpt) -> io::Result<()> {
+        self.selector_id.associate_selector(epoll)?;
+        epoll.add(&self.as_raw_fd(), token, interest, opts)
+    }
+
+    fn modify(
+        &self,
+        epoll: &Epoll,
+        token: Token,
+        interest: Ready,
+        opts: EpollOpt,
+    ) -> io::Result<()> {
+        epoll.modify(&self.as_raw_fd(), token, interest, opts)
+    }
+
+    fn delete(&self, epoll: &Epoll) -> io::Result<()> {
+        epoll.delete(&self.as_raw_fd())
+    }
+}
+
+impl FromRawFd for UnixSeqpacket {
+    unsafe fn from_raw_fd(fd: RawFd) -> UnixSeqpacket {
+        UnixSeqpacket::from_fd(OwnedFd::from_raw_fd(fd))
+    }
+}
+
+impl IntoRawFd for UnixSeqpacket {
+    fn into_raw_fd(self) -> RawFd {
+        self.inner.into_raw_fd()
+    }
+}
+
+impl AsRawFd for UnixSeqpacket {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+impl UnixSeqpacketListener {
+    /// Bind a listening socket to `path`, with a backlog of 128.
+    pub fn bind<P: AsRef<Path>>(path: P) -> io::Result<UnixSeqpacketListener> {
+        UnixSeqpacketListener::bind_addr(&SocketAddr::from_pathname(path)?)
+    }
+
+    /// Bind a listening socket to `addr`, a path or an abstract name.
+    pub fn bind_addr(addr: &SocketAddr) -> io::Result<UnixSeqpacketListener> {
+        let socket = seqpacket_socket()?;
+        let (storage, len) = socket::unix_socket_addr(addr);
+
+        syscall!(bind(
+            socket.as_raw_fd(),
+            &storage as *const _ as *const libc::sockaddr,
+            len
+        ))?;
+        syscall!(listen(socket.as_raw_fd(), 128))?;
+
+        Ok(UnixSeqpacketListener {
+            inner: socket,
+            selector_id: SelectorId::new(),
+        })
+    }
+
+    /// Accept a connection with accept4(2), the socket is non-blocking and
+    /// close-on-exec.
+    pub fn accept(&self) -> io::Result<(UnixSeqpacket, SocketAddr)> {
+        let mut storage: libc::sockaddr_un = unsafe { mem::zeroed() };
+        let (fd, len) = socket::accept4(self.as_raw_fd(), &mut storage)?;
+
+        let socket = unsafe { UnixSeqpacket::from_raw_fd(fd) };
+        let addr = socket::to_unix_addr(&storage, len)?;
+
+        Ok((socket, addr))
+    }
+
+    /// Accept up to `max` connections, like `TcpListener::accept_batch`.
+    pub fn accept_batch(&self, max: usize) -> io::Result<Vec<(UnixSeqpacket, SocketAddr)>> {
+        socket::accept_batch(max, || self.accept())
+    }
+
+    pub fn try_clone(&self) -> io::Result<UnixSeqpacketListener> {
+        self.inner.try_clone().map(|fd| UnixSeqpacketListener {
+            inner: fd,
+            selector_id: self.selector_id.clone(),
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        unix_name(self.as_raw_fd(), libc::getsockname)
+    }
+
+    pub fn take_error(&self) -> io::Result<Option<io::Error>> {
+        take_error(self.as_raw_fd())
+    }
+
+    pub fn set_sockopt<O: SockOpt>(&self, opt: O, value: O::Value) -> io::Result<()> {
+        opt.set(self.as_raw_fd(), value)
+    }
+
+    pub fn sockopt<O: SockOpt>(&self, opt: O) -> io::Result<O::Value> {
+        opt.get(self.as_raw_fd())
+    }
+}
+
+impl Source for UnixSeqpacketListener {
+    fn add(&self, epoll: &Epoll, token: Token, interest: Ready, opts: EpollOpt) -> io::Result<()> {
+        self.selector_id.associate_selector(epoll)?;
+        epoll.add(&self.as_raw_fd(), token, interest, opts)
+    }
+
+    fn modify(
+        &self,
+        epoll: &Epoll,
+        token: Token,
+        interest: Ready,
+        opts: EpollOpt,
+    ) -> io::Result<()> {
+        epoll.modify(&self.as_raw_fd(), token, interest, opts)
+    }
+
+    fn delete(&self, epoll: &Epoll) -> io::Result<()> {
+        epoll.delete(&self.as_raw_fd())
+    }
+}
+
+impl FromRawFd for UnixSeqpacketListener {
+    unsafe fn from_raw_fd(fd: RawFd) -> UnixSeqpacketListener {
+        UnixSeqpacketListener {
+            inner: OwnedFd::from_raw_fd(fd),
+            selector_id: SelectorId::new(),
+        }
+    }
+}
+
+impl IntoRawFd for UnixSeqpacketListener {
+    fn into_raw_fd(self) -> RawFd {
+        self.inner.into_raw_fd()
+    }
+}
+
+impl AsRawFd for UnixSeqpacketListener {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+fn send_with_fds(fd: RawFd, buf: &[u8], fds: &[RawFd]) -> io::Result<usize> {
+    if fds.len() > MAX_FDS {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "too many file descriptors"));
+    }
+
+    let data = unsafe {
+        std::slice::from_raw_parts(fds.as_ptr() as *const u8, mem::size_of_val(fds))
+    };
+
+    let mut control = CmsgBuf::with_capacity(cmsg::space(data.len()));
+    if !fds.is_empty() {
+        control.push(libc::SOL_SOCKET, libc::SCM_RIGHTS, data);
+    }
+
+    socket::sendmsg::<()>(fd, buf, None, &control, 0)
+}
+
+fn recv_with_fds(fd: RawFd, buf: &mut [u8], fds: &mut Vec<OwnedFd>) -> io::Result<usize> {
+    let mut control = CmsgBuf::with_capacity(cmsg::space(MAX_FDS * mem::size_of::<RawFd>()));
+
+    let (len, _, flags) =
+        socket::recvmsg::<()>(fd, buf, None, &mut control, libc::MSG_CMSG_CLOEXEC)?;
+
+    for cmsg in control.iter() {
+        if (cmsg.level, cmsg.ty) != (libc::SOL_SOCKET, libc::SCM_RIGHTS) {
+            continue;
+        }
+
+        for raw in cmsg.data.chunks_exact(mem::size_of::<RawFd>()) {
+            let raw = RawFd::from_ne_bytes(raw.try_into().unwrap());
+            fds.push(unsafe { OwnedFd::from_raw_fd(raw) });
+        }
+    }
+
+    if flags & libc::MSG_CTRUNC != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "control messages truncated, file descriptors were lost",
+        ));
+    }
+
+    Ok(len)
+}
+
+fn peer_cred(fd: RawFd) -> io::Result<UCred> {
+    let cred: libc::ucred = socket::getsockopt(fd, libc::SOL_SOCKET, libc::SO_PEERCRED)?;
+
+    Ok(UCred {
+        pid: cred.pid,
+        uid: cred.uid,
+        gid: cred.gid,
+    })
+}
+
+fn send_with_cred(fd: RawFd, buf: &[u8]) -> io::Result<usize> {
+    let current = UCred::current();
+    let cred = libc::ucred {
+        pid: current.pid,
+        uid: current.uid,
+        gid: current.gid,
+    };
+
+    let mut control = CmsgBuf::with_capacity(cmsg::space(mem::size_of::<libc::ucred>()));
+    control.push_value(libc::SOL_SOCKET, libc::SCM_CREDENTIALS, &cred);
+
+    socket::sendmsg::<()>(fd, buf, None, &control, 0)
+}
+
+fn recv_with_cred(fd: RawFd, buf: &mut [u8]) -> io::Result<(usize, Option<UCred>)> {
+    let mut control = CmsgBuf::with_capacity(cmsg::space(mem::size_of::<libc::ucred>()));
+
+    let (len, _, flags) =
+        socket::recvmsg::<()>(fd, buf, None, &mut control, libc::MSG_CMSG_CLOEXEC)?;
+
+    let mut cred = None;
+
+    for cmsg in control.iter() {
+        match (cmsg.level, cmsg.ty) {
+            (libc::SOL_SOCKET, libc::SCM_CREDENTIALS) => {
+                cred = cmsg.read::<libc::ucred>().map(|cred| UCred {
+                    pid: cred.pid,
+                    uid: cred.uid,
+                    gid: cred.gid,
+                });
+            }
+            // the peer may pass file descriptors, which are installed in this
+            // process anyway: close them
+            (libc::SOL_SOCKET, libc::SCM_RIGHTS) => {
+                for raw in cmsg.data.chunks_exact(mem::size_of::<RawFd>()) {
+                    let raw = RawFd::from_ne_bytes(raw.try_into().unwrap());
+                    drop(unsafe { OwnedFd::from_raw_fd(raw) });
+                }
+            }
+            _ => (),
+        }
+    }
+
+    if flags & libc::MSG_CTRUNC != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "control messages truncated, credentials may be lost",
+        ));
+    }
+
+    Ok((len, cred))
+}
+
+fn seqpacket_socket() -> io::Result<OwnedFd> {
+    let fd = socket::socket(libc::AF_UNIX, libc::SOCK_SEQPACKET, 0)?;
+
+    Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+}
+
+type NameFn =
+    unsafe extern "C" fn(libc::c_int, *mut libc::sockaddr, *mut libc::socklen_t) -> libc::c_int;
+
+fn unix_name(fd: RawFd, f: NameFn) -> io::Result<SocketAddr> {
+    let mut storage: libc::sockaddr_un = unsafe { mem::zeroed() };
+    let mut len = mem::size_of::<libc::sockaddr_un>() as libc::socklen_t;
+
+    if unsafe { f(fd, &mut storage as *mut _ as *mut libc::sockaddr, &mut len) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    socket::to_unix_addr(&storage, len)
+}
+
+fn take_error(fd: RawFd) -> io::Result<Option<io::Error>> {
+    let err: libc::c_int = socket::getsockopt(fd, libc::SOL_SOCKET, libc::SO_ERROR)?;
+
+    if err == 0 {
+        Ok(None)
+    } else {
+        Ok(Some(io::Error::from_raw_os_error(err)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs::File;
+    use std::io::{ErrorKind, Read, Write};
+    use std::os::linux::net::SocketAddrExt;
+    use std::os::unix::fs::PermissionsExt;
+    use std::os::unix::io::{AsRawFd, IntoRawFd, OwnedFd};
+    use std::os::unix::net::{self, SocketAddr};
+    use std::time::Duration;
+
+    use crate::epoll::{Epoll, EpollOpt, Events, Ready, Token};
+    use crate::sys::pipe;
+
+    use crate::sys::socket::PassCred;
+
+    use super::{
+        UCred, UnixDatagram, UnixListener, UnixListenerBuilder, UnixSeqpacket,
+        UnixSeqpacketListener, UnixStream,
+    };
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        let name = format!("queen-io-{}-{}.sock", name, std::process::id());
+        let path = std::env::temp_dir().join(name);
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn datagram() {
+        let path = temp_path("datagram");
+
+        let server = UnixDatagram::bind(&path).unwrap();
+        let client = UnixDatagram::unbound().unwrap();
+
+        let mut buf = [0; 16];
+        let err = server.recv_from(&mut buf).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::WouldBlock);
+
+        let epoll = Epoll::new().unwrap();
+        let mut events = Events::with_capacity(8);
+        epoll
+            .add(&server, Token(0), Ready::readable(), EpollOpt::edge())
+            .unwrap();
+
+        client.send_to(b"hello", &path).unwrap();
+
+        epoll
+            .wait(&mut events, Some(Duration::from_secs(5)))
+            .unwrap();
+        assert_eq!(events.len(), 1);
+
+        let (n, addr) = server.recv_from(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"hello");
+        assert!(addr.is_unnamed());
+
+        let (a, b) = UnixDatagram::pair().unwrap();
+        a.send(b"one").unwrap();
+        a.send(b"two").unwrap();
+        assert_eq!(b.recv(&mut buf).unwrap(), 3);
+        assert_eq!(b.recv(&mut buf).unwrap(), 3);
+        assert_eq!(&buf[..3], b"two");
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn seqpacket() {
+        let path = temp_path("seqpacket");
+
+        let listener = UnixSeqpacketListener::bind(&path).unwrap();
+        assert_eq!(listener.local_addr().unwrap().as_pathname(), Some(path.as_path()));
+
+        let err = listener.accept().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::WouldBlock);
+
+        let client = UnixSeqpacket::connect(&path).unwrap();
+        let (server, _) = listener.accept().unwrap();
+        assert_eq!(client.peer_addr().unwrap().as_pathname(), Some(path.as_path()));
+
+        client.send(b"hello").unwrap();
+        client.send(b"world!").unwrap();
+
+        let epoll = Epoll::new().unwrap();
+        let mut events = Events::with_capacity(8);
+        epoll
+            .add(&server, Token(0), Ready::readable(), EpollOpt::level())
+            .unwrap();
+        epoll
+            .wait(&mut events, Some(Duration::from_secs(5)))
+            .unwrap();
+
+        // message boundaries are kept
+        let mut buf = [0; 64];
+        assert_eq!(server.peek(&mut buf).unwrap(), 5);
+        assert_eq!(server.recv(&mut buf).unwrap(), 5);
+        assert_eq!(server.recv(&mut buf).unwrap(), 6);
+        assert_eq!(&buf[..6], b"world!");
+
+        let err = server.recv(&mut buf).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::WouldBlock);
+
+        drop(client);
+        assert_eq!(server.recv(&mut buf).unwrap(), 0);
+        assert!(server.take_error().unwrap().is_none());
+
+        let (a, b) = UnixSeqpacket::pair().unwrap();
+        a.send(b"x").unwrap();
+        assert_eq!(b.recv(&mut buf).unwrap(), 1);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn pass_fds() {
+        let (a, b) = UnixStream::pair().unwrap();
+        let (reader, mut writer) = pipe::pipe().unwrap();
+
+        a.send_with_fds(b"fds", &[reader.as_raw_fd(), writer.as_raw_fd()])
+            .unwrap();
+        drop(reader);
+
+        let mut buf = [0; 16];
+        let mut fds: Vec<OwnedFd> = Vec::new();
+        assert_eq!(b.recv_with_fds(&mut buf, &mut fds).unwrap(), 3);
+        assert_eq!(fds.len(), 2);
+
+        for fd in &fds {
+            let flags = unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_GETFD) };
+            assert!(flags & libc::FD_CLOEXEC != 0);
+        }
+
+        writer.write_all(b"through").unwrap();
+        drop(writer);
+        fds.truncate(1);
+
+        let mut received = String::new();
+        File::from(fds.pop().unwrap())
+            .read_to_string(&mut received)
+            .unwrap();
+        assert_eq!(received, "through");
+
+        let (a, b) = UnixDatagram::pair().unwrap();
+        a.send_with_fds(b"none", &[]).unwrap();
+        assert_eq!(b.recv_with_fds(&mut buf, &mut fds).unwrap(), 4);
+        assert!(fds.is_empty());
+
+        let err = a.send_with_fds(b"", &[0; 254]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn credentials() {
+        let (a, b) = UnixStream::pair().unwrap();
+        assert_eq!(a.peer_cred().unwrap(), UCred::current());
+
+        b.set_sockopt(PassCred, true).unwrap();
+        a.send_with_cred(b"cred").unwrap();
+
+        let mut buf = [0; 16];
+        let (n, cred) = b.recv_with_cred(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"cred");
+        assert_eq!(cred, Some(UCred::current()));
+
+        let (a, b) = UnixDatagram::pair().unwrap();
+        a.send(b"none").unwrap();
+        assert_eq!(b.recv_with_cred(&mut buf).unwrap(), (4, None));
+
+        // passed file descriptors are closed
+        let (reader, writer) = pipe::pipe().unwrap();
+        a.send_with_fds(b"fd", &[writer.as_raw_fd()]).unwrap();
+        drop(writer);
+
+        assert_eq!(b.recv_with_cred(&mut buf).unwrap(), (2, None));
+        assert_eq!((&reader).read(&mut buf).unwrap(), 0);
+    }
+
+    #[test]
+    fn cred_filter() {
+        let path = temp_path("cred-filter");
+        let mut listener = UnixListener::bind(&path).unwrap();
+        let uid = UCred::current().uid;
+
+        listener.set_cred_filter(move |cred| cred.uid != uid);
+        let _client = UnixStream::connect(&path).unwrap();
+
+        let err = listener.accept().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::WouldBlock);
+
+        listener.set_cred_filter(move |cred| cred.uid == uid);
+        let _client = UnixStream::connect(&path).unwrap();
+
+        let (stream, _) = listener.accept().unwrap();
+        assert_eq!(stream.peer_cred().unwrap().uid, uid);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn abstract_addr() {
+        let name = format!("queen-io-abstract-{}", std::process::id());
+        let addr = SocketAddr::from_abstract_name(&name).unwrap();
+
+        let listener = UnixListener::bind_addr(&addr).unwrap();
+        assert_eq!(
+            listener.local_addr().unwrap().as_abstract_name(),
+            Some(name.as_bytes())
+        );
+
+        let stream = UnixStream::connect_addr(&addr).unwrap();
+        assert!(stream.take_error().unwrap().is_none());
+
+        let (_, peer) = listener.accept().unwrap();
+        assert!(peer.is_unnamed());
+        assert_eq!(
+            stream.peer_addr().unwrap().as_abstract_name(),
+            Some(name.as_bytes())
+        );
+
+        let packets = SocketAddr::from_abstract_name(format!("{}-seqpacket", name)).unwrap();
+        let listener = UnixSeqpacketListener::bind_addr(&packets).unwrap();
+        UnixSeqpacket::connect_addr(&packets).unwrap();
+        assert!(listener.accept().is_ok());
+
+        drop(listener);
+        let err = UnixSeqpacket::connect_addr(&packets).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ConnectionRefused);
+    }
+
+    #[test]
+    fn listener_builder() {
+        let path = temp_path("builder");
+
+        // a socket file left behind by a listener which is gone
+        drop(net::UnixListener::bind(&path).unwrap());
+
+        let err = UnixListener::bind(&path).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::AddrInUse);
+
+        let listener = UnixListenerBuilder::new()
+            .mode(0o600)
+            .remove_stale(true)
+            .unlink_on_drop(true)
+            .bind(&path)
+            .unwrap();
+
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        // a live listener is not removed
+        let err = UnixListener::builder()
+            .remove_stale(true)
+            .bind(&path)
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::AddrInUse);
+
+        let clone = listener.try_clone().unwrap();
+        drop(clone);
+        assert!(path.exists());
+
+        drop(listener);
+        assert!(!path.exists());
+
+        let listener = UnixListener::builder()
+            .unlink_on_drop(true)
+            .bind(&path)
+            .unwrap();
+        let fd = listener.into_raw_fd();
+        assert!(path.exists());
+
+        unsafe { libc::close(fd) };
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,179 @@
+//! Zero-copy transfers: `sendfile` from a file to a socket, and
+//! `SplicePipe::splice_between` between any two fds.
+//!
+//! `splice_between` is a method of `SplicePipe` rather than a free function:
+//! when the destination would block, the bytes already taken from the
+//! source stay in the internal pipe, and the next call must write them
+//! first. A free function creating its pipe on every call would lose them,
+//! so the pipe pair is kept by the caller, one per direction.
+
+use std::fs::File;
+use std::io::{self, ErrorKind};
+use std::os::unix::io::AsRawFd;
+
+use libc;
+
+use crate::sys::pipe::{self, PipeReader, PipeWriter, SPLICE_F_MORE, SPLICE_F_MOVE};
+
+use super::tcp::TcpStream;
+
+/// sendfile(2) copies up to `len` bytes of `file`, starting at `offset`, to
+/// `stream` inside the kernel.
+/// view: `<http://man7.org/linux/man-pages/man2/sendfile.2.html>`
+///
+/// The call is repeated until `len` bytes are sent, the file reaches end of
+/// file, or the socket would block. The number of bytes sent is returned,
+/// `WouldBlock` is only returned if nothing could be sent at all. To resume,
+/// wait for `Ready::writable()` and call again with `offset` advanced by the
+/// bytes already sent.
+pub fn sendfile(stream: &TcpStream, file: &File, offset: u64, len: usize) -> io::Result<usize> {
+    let mut offset = offset as libc::off_t;
+
+    pipe::transfer(len, |remaining| {
+        syscall!(sendfile(
+            stream.as_raw_fd(),
+            file.as_raw_fd(),
+            &mut offset,
+            remaining
+        ))
+    })
+}
+
+/// Moves data between two file descriptors, e.g. two sockets of a proxy,
+/// through an internal pipe with splice(2), without copying it to user space.
+///
+/// Bytes taken from the source are held in the pipe until the destination
+/// accepts them, so the same `SplicePipe` must be used for every call on one
+/// direction of a connection.
+#[derive(Debug)]
+pub struct SplicePipe {
+    reader: PipeReader,
+    writer: PipeWriter,
+    capacity: usize,
+    pending: usize,
+}
+
+impl SplicePipe {
+    pub fn new() -> io::Result<SplicePipe> {
+        let (reader, writer) = pipe::pipe()?;
+        let capacity = writer.capacity()?;
+
+        Ok(SplicePipe {
+            reader,
+            writer,
+            capacity,
+            pending: 0,
+        })
+    }
+
+    /// The number of bytes read from the source but not yet written to the
+    /// destination.
+    pub fn pending(&self) -> usize {
+        self.pending
+    }
+
+    /// Move as much data as possible from `src` to `dst`, returning the number
+    /// of bytes written to `dst`.
+    ///
+    /// Like `read`, this should be called until it returns `WouldBlock` when
+    /// the sources are registered edge-triggered; the caller then waits for
+    /// `src` readable or, if `pending` is not zero, for `dst` writable.
+    /// `Ok(0)` means `src` reached end of file and the pipe is drained.
+    pub fn splice_between<S: AsRawFd, D: AsRawFd>(
+        &mut self,
+        src: &S,
+        dst: &D,
+    ) -> io::Result<usize> {
+        let mut moved = 0;
+
+        loop {
+            if self.pending > 0 {
+                match pipe::splice(
+                    &self.reader,
+                    None,
+                    dst,
+                    None,
+                    self.pending,
+                    SPLICE_F_MOVE | SPLICE_F_MORE,
+                ) {
+                    Ok(n) => {
+                        self.pending -= n;
+                        moved += n;
+                    }
+                    Err(ref e) if e.kind() == ErrorKind::WouldBlock && moved > 0 => {
+                        return Ok(moved)
+                    }
+                    Err(e) => return Err(e),
+                }
+
+                if self.pending > 0 {
+                    return Ok(moved);
+                }
+            }
+
+            match pipe::splice(src, None, &self.writer, None, self.capacity, SPLICE_F_MOVE) {
+                Ok(0) => return Ok(moved),
+                Ok(n) => self.pending += n,
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock && moved > 0 => return Ok(moved),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs::{self, File};
+    use std::io::{ErrorKind, Read, Write};
+    use std::net::TcpListener;
+
+    use crate::net::tcp::TcpStream;
+    use crate::sys::pipe;
+
+    use super::{sendfile, SplicePipe};
+
+    #[test]
+    fn sendfile_to_stream() {
+        let path = std::env::temp_dir().join(format!("queen-io-sendfile-{}", std::process::id()));
+        fs::write(&path, b"hello sendfile").unwrap();
+        let file = File::open(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (mut peer, _) = listener.accept().unwrap();
+
+        assert_eq!(sendfile(&stream, &file, 6, 64).unwrap(), 8);
+        drop(stream);
+
+        let mut buf = Vec::new();
+        peer.read_to_end(&mut buf).unwrap();
+        assert_eq!(buf, b"sendfile");
+    }
+
+    #[test]
+    fn splice_pipe_to_stream() {
+        let (reader, mut writer) = pipe::pipe().unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (mut peer, _) = listener.accept().unwrap();
+
+        let mut splice = SplicePipe::new().unwrap();
+
+        let err = splice.splice_between(&reader, &stream).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::WouldBlock);
+
+        writer.write_all(b"hello splice").unwrap();
+        assert_eq!(splice.splice_between(&reader, &stream).unwrap(), 12);
+        assert_eq!(splice.pending(), 0);
+
+        drop(writer);
+        assert_eq!(splice.splice_between(&reader, &stream).unwrap(), 0);
+        drop(stream);
+
+        let mut buf = Vec::new();
+        peer.read_to_end(&mut buf).unwrap();
+        assert_eq!(buf, b"hello splice");
+    }
+}
@@ -85,7 +85,7 @@ impl Events {
         })
     }
 
-    pub fn iter(&self) -> Iter {
+    pub fn iter(&self) -> Iter<'_> {
         Iter {
             inner: self,
             pos: 0
@@ -1,6 +1,6 @@
 use std::convert::TryInto;
 use std::os::unix::io::RawFd;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use std::{cmp, io};
 
 mod event;
@@ -11,45 +11,46 @@ pub use event::{Event, Events};
 pub use ready::Ready;
 
 pub fn poll(evts: &mut Events, timeout: Option<Duration>) -> io::Result<i32> {
-    let timeout = timeout
-        .map(|to| cmp::min(to.as_millis(), libc::c_int::MAX as u128) as libc::c_int)
-        .unwrap_or(-1);
-
-    let ret = unsafe {
-        libc::poll(
-            evts.events.as_mut_ptr(),
-            evts.len().try_into().unwrap(),
-            timeout,
-        )
-    };
-    if ret < 0 {
-        let err = io::Error::last_os_error();
-        if err.kind() != io::ErrorKind::Interrupted {
-            return Err(err);
-        }
-    }
-
-    Ok(ret)
+    poll_retry(&mut evts.events, timeout)
 }
 
 pub fn wait(fd: RawFd, readiness: Ready, timeout: Option<Duration>) -> io::Result<Ready> {
-    let timeout = timeout
-        .map(|to| cmp::min(to.as_millis(), libc::c_int::MAX as u128) as libc::c_int)
-        .unwrap_or(-1);
-
-    let mut pollfd = libc::pollfd {
+    let mut pollfd = [libc::pollfd {
         fd,
         events: ioevent_to_poll(readiness),
         revents: 0,
-    };
+    }];
+
+    poll_retry(&mut pollfd, timeout)?;
+
+    Ok(poll_to_ioevent(pollfd[0].revents))
+}
+
+// poll(2) is never restarted after a signal handler, not even with
+// SA_RESTART, e.g. the SIGCHLD handler of `process::Command::spawn`: it is
+// called again with the time left
+fn poll_retry(fds: &mut [libc::pollfd], timeout: Option<Duration>) -> io::Result<i32> {
+    let deadline = timeout.and_then(|to| Instant::now().checked_add(to));
+
+    loop {
+        let timeout = deadline
+            .map(|deadline| {
+                // rounded up, not to return before the deadline
+                let to = deadline.saturating_duration_since(Instant::now());
+                let millis = to.as_nanos().div_ceil(1_000_000);
+                cmp::min(millis, libc::c_int::MAX as u128) as libc::c_int
+            })
+            .unwrap_or(-1);
+
+        let ret = unsafe { libc::poll(fds.as_mut_ptr(), fds.len().try_into().unwrap(), timeout) };
+
+        if ret >= 0 {
+            return Ok(ret);
+        }
 
-    let ret = unsafe { libc::poll(&mut pollfd, 1, timeout) };
-    if ret < 0 {
         let err = io::Error::last_os_error();
         if err.kind() != io::ErrorKind::Interrupted {
             return Err(err);
         }
     }
-
-    Ok(poll_to_ioevent(pollfd.revents))
 }
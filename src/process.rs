@@ -0,0 +1,698 @@
+use std::ffi::OsStr;
+use std::hint;
+use std::io::{self, Read, Write};
+use std::mem;
+use std::os::unix::io::{AsRawFd, IntoRawFd, RawFd};
+use std::path::Path;
+use std::process::{self, ExitStatus, Stdio};
+use std::ptr;
+use std::sync::atomic::{AtomicI32, AtomicUsize, Ordering};
+use std::sync::{Mutex, OnceLock};
+
+use crate::epoll::{Epoll, EpollOpt, Ready, Source, Token};
+use crate::sys::fd::FileDesc;
+
+/// A process builder, like `std::process::Command`, whose children have
+/// non-blocking stdio pipes and a pollable exit notification.
+#[derive(Debug)]
+pub struct Command {
+    inner: process::Command,
+}
+
+impl Command {
+    /// Create a command for `program`, stdin, stdout and stderr default to
+    /// `Stdio::piped()`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use queen_io::process::Command;
+    ///
+    /// let child = Command::new("true").spawn().unwrap();
+    /// ```
+    pub fn new<S: AsRef<OsStr>>(program: S) -> Command {
+        let mut inner = process::Command::new(program);
+
+        inner
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+
+        Command { inner }
+    }
+
+    pub fn arg<S: AsRef<OsStr>>(&mut self, arg: S) -> &mut Command {
+        self.inner.arg(arg);
+        self
+    }
+
+    pub fn args<I, S>(&mut self, args: I) -> &mut Command
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        self.inner.args(args);
+        self
+    }
+
+    pub fn env<K, V>(&mut self, key: K, val: V) -> &mut Command
+    where
+        K: AsRef<OsStr>,
+        V: AsRef<OsStr>,
+    {
+        self.inner.env(key, val);
+        self
+    }
+
+    pub fn envs<I, K, V>(&mut self, vars: I) -> &mut Command
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<OsStr>,
+        V: AsRef<OsStr>,
+    {
+        self.inner.envs(vars);
+        self
+    }
+
+    pub fn env_remove<K: AsRef<OsStr>>(&mut self, key: K) -> &mut Command {
+        self.inner.env_remove(key);
+        self
+    }
+
+    pub fn env_clear(&mut self) -> &mut Command {
+        self.inner.env_clear();
+        self
+    }
+
+    pub fn current_dir<P: AsRef<Path>>(&mut self, dir: P) -> &mut Command {
+        self.inner.current_dir(dir);
+        self
+    }
+
+    pub fn stdin<T: Into<Stdio>>(&mut self, cfg: T) -> &mut Command {
+        self.inner.stdin(cfg);
+        self
+    }
+
+    pub fn stdout<T: Into<Stdio>>(&mut self, cfg: T) -> &mut Command {
+        self.inner.stdout(cfg);
+        self
+    }
+
+    pub fn stderr<T: Into<Stdio>>(&mut self, cfg: T) -> &mut Command {
+        self.inner.stderr(cfg);
+        self
+    }
+
+    /// Spawn the child, the piped stdio handles are switched to non-blocking
+    /// mode, and a pidfd is opened for the exit notification.
+    /// view: `<http://man7.org/linux/man-pages/man2/pidfd_open.2.html>`
+    ///
+    /// On kernels without pidfd_open (before 5.3), a SIGCHLD handler is
+    /// installed instead, see `Child`. The handler is process-wide and stays
+    /// installed: from then on, whenever any child of the process exits,
+    /// spawned by this crate or not, a blocked `Epoll::wait` in any thread
+    /// fails with `Interrupted` and must be called again. `poll::wait`
+    /// retries by itself.
+    pub fn spawn(&mut self) -> io::Result<Child> {
+        self.spawn_with(pidfd_open)
+    }
+
+    fn spawn_with<F>(&mut self, open: F) -> io::Result<Child>
+    where
+        F: FnOnce(u32) -> io::Result<FileDesc>,
+    {
+        let mut child = self.inner.spawn()?;
+
+        let exit = match open(child.id()) {
+            Ok(pidfd) => Ok(ExitNotify::Pidfd(pidfd)),
+            Err(ref e) if e.raw_os_error() == Some(libc::ENOSYS) => {
+                SigchldSlot::new().map(ExitNotify::Sigchld)
+            }
+            Err(e) => Err(e),
+        };
+
+        let exit = match exit {
+            Ok(exit) => exit,
+            Err(err) => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(err);
+            }
+        };
+
+        let stdin = child.stdin.take().map(ChildStdin::new).transpose()?;
+        let stdout = child.stdout.take().map(ChildStdout::new).transpose()?;
+        let stderr = child.stderr.take().map(ChildStderr::new).transpose()?;
+
+        Ok(Child {
+            inner: child,
+            exit,
+            stdin,
+            stdout,
+            stderr,
+        })
+    }
+}
+
+fn pidfd_open(pid: u32) -> io::Result<FileDesc> {
+    let pidfd = unsafe { libc::syscall(libc::SYS_pidfd_open, pid as libc::pid_t, 0) };
+
+    if pidfd == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(unsafe { FileDesc::new(pidfd as RawFd) })
+}
+
+/// What becomes readable once the child has exited.
+#[derive(Debug)]
+enum ExitNotify {
+    Pidfd(FileDesc),
+    Sigchld(SigchldSlot),
+}
+
+impl ExitNotify {
+    fn as_raw_fd(&self) -> RawFd {
+        match self {
+            ExitNotify::Pidfd(pidfd) => pidfd.as_raw_fd(),
+            ExitNotify::Sigchld(slot) => slot.fd.as_raw_fd(),
+        }
+    }
+}
+
+/// The most children waiting for SIGCHLD at the same time.
+const SIGCHLD_SLOTS: usize = 256;
+
+/// An eventfd written by the SIGCHLD handler, `busy` counts the handlers
+/// writing to it so that it is not closed under them.
+struct Slot {
+    fd: AtomicI32,
+    busy: AtomicUsize,
+}
+
+static SLOTS: [Slot; SIGCHLD_SLOTS] = [const {
+    Slot {
+        fd: AtomicI32::new(-1),
+        busy: AtomicUsize::new(0),
+    }
+}; SIGCHLD_SLOTS];
+
+static OLD_SIGCHLD: OnceLock<libc::sigaction> = OnceLock::new();
+
+/// The exit notification of a child without pidfd: an eventfd in `SLOTS`,
+/// written on every SIGCHLD the process receives.
+#[derive(Debug)]
+struct SigchldSlot {
+    index: usize,
+    fd: FileDesc,
+}
+
+impl SigchldSlot {
+    fn new() -> io::Result<SigchldSlot> {
+        install_sigchld_handler()?;
+
+        let fd = syscall!(eventfd(0, libc::EFD_CLOEXEC | libc::EFD_NONBLOCK))?;
+        let fd = unsafe { FileDesc::new(fd) };
+
+        let index = SLOTS
+            .iter()
+            .position(|slot| {
+                slot.fd
+                    .compare_exchange(-1, fd.as_raw_fd(), Ordering::SeqCst, Ordering::SeqCst)
+                    .is_ok()
+            })
+            .ok_or_else(|| io::Error::other("too many children waiting for SIGCHLD"))?;
+
+        let slot = SigchldSlot { index, fd };
+
+        // the child may have exited before the slot was taken
+        (&slot.fd).write_all(&1u64.to_ne_bytes())?;
+
+        Ok(slot)
+    }
+
+    /// Reset the eventfd, before checking whether the child has exited.
+    fn clear(&self) -> io::Result<()> {
+        let mut buf = [0u8; 8];
+
+        match (&self.fd).read(&mut buf) {
+            Ok(_) => Ok(()),
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl Drop for SigchldSlot {
+    fn drop(&mut self) {
+        let slot = &SLOTS[self.index];
+        slot.fd.store(-1, Ordering::SeqCst);
+
+        while slot.busy.load(Ordering::SeqCst) != 0 {
+            hint::spin_loop();
+        }
+    }
+}
+
+fn install_sigchld_handler() -> io::Result<()> {
+    static INSTALLED: Mutex<bool> = Mutex::new(false);
+
+    let mut installed = INSTALLED.lock().unwrap_or_else(|e| e.into_inner());
+
+    if *installed {
+        return Ok(());
+    }
+
+    let mut old: libc::sigaction = unsafe { mem::zeroed() };
+    syscall!(sigaction(libc::SIGCHLD, ptr::null(), &mut old))?;
+    let _ = OLD_SIGCHLD.set(old);
+
+    let mut action: libc::sigaction = unsafe { mem::zeroed() };
+    action.sa_sigaction = on_sigchld as SigAction as usize;
+    action.sa_flags = libc::SA_SIGINFO | libc::SA_RESTART | libc::SA_NOCLDSTOP;
+    syscall!(sigemptyset(&mut action.sa_mask))?;
+    syscall!(sigaction(libc::SIGCHLD, &action, ptr::null_mut()))?;
+
+    *installed = true;
+
+    Ok(())
+}
+
+extern "C" fn on_sigchld(
+    signum: libc::c_int,
+    info: *mut libc::siginfo_t,
+    context: *mut libc::c_void,
+) {
+    let errno = unsafe { *libc::__errno_location() };
+    let one = 1u64.to_ne_bytes();
+
+    for slot in SLOTS.iter() {
+        slot.busy.fetch_add(1, Ordering::SeqCst);
+
+        let fd = slot.fd.load(Ordering::SeqCst);
+
+        if fd >= 0 {
+            unsafe { libc::write(fd, one.as_ptr() as *const libc::c_void, one.len()) };
+        }
+
+        slot.busy.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    // chain the handler installed before
+    if let Some(old) = OLD_SIGCHLD.get() {
+        let handler = old.sa_sigaction;
+
+        if handler != libc::SIG_DFL && handler != libc::SIG_IGN {
+            unsafe {
+                if old.sa_flags & libc::SA_SIGINFO != 0 {
+                    let handler: SigAction = mem::transmute(handler);
+                    handler(signum, info, context);
+                } else {
+                    let handler: SigHandler = mem::transmute(handler);
+                    handler(signum);
+                }
+            }
+        }
+    }
+
+    unsafe { *libc::__errno_location() = errno };
+}
+
+type SigAction = extern "C" fn(libc::c_int, *mut libc::siginfo_t, *mut libc::c_void);
+type SigHandler = extern "C" fn(libc::c_int);
+
+/// A spawned child process.
+///
+/// Registering a `Child` with `Epoll` registers its pidfd, which becomes
+/// readable once the child has exited. Call `try_wait` to reap it.
+///
+/// Without pidfd support, an eventfd written by a SIGCHLD handler is
+/// registered instead. It becomes readable when any child of the process
+/// exits, so `try_wait` may return `None` after a wakeup. The handler that
+/// was installed before is still called. As with any signal handler,
+/// `Epoll::wait` returns `Interrupted` when a SIGCHLD arrives during the
+/// wait, call it again.
+///
+/// Like `std::process::Child`, dropping a `Child` neither kills nor reaps
+/// the process: call `wait`, or `try_wait` until it returns the status,
+/// otherwise the exited child stays a zombie until this process exits.
+#[derive(Debug)]
+pub struct Child {
+    inner: process::Child,
+    exit: ExitNotify,
+    pub stdin: Option<ChildStdin>,
+    pub stdout: Option<ChildStdout>,
+    pub stderr: Option<ChildStderr>,
+}
+
+impl Child {
+    pub fn id(&self) -> u32 {
+        self.inner.id()
+    }
+
+    pub fn kill(&mut self) -> io::Result<()> {
+        self.inner.kill()
+    }
+
+    /// Reap the child if it has exited, `None` if it is still running.
+    pub fn try_wait(&mut self) -> io::Result<Option<ExitStatus>> {
+        if let ExitNotify::Sigchld(slot) = &self.exit {
+            slot.clear()?;
+        }
+
+        self.inner.try_wait()
+    }
+
+    /// Block until the child exits, stdin is closed first so that the child
+    /// does not wait for input forever.
+    pub fn wait(&mut self) -> io::Result<ExitStatus> {
+        drop(self.stdin.take());
+        self.inner.wait()
+    }
+}
+
+impl AsRawFd for Child {
+    fn as_raw_fd(&self) -> RawFd {
+        self.exit.as_raw_fd()
+    }
+}
+
+impl Source for Child {
+    fn add(&self, epoll: &Epoll, token: Token, interest: Ready, opts: EpollOpt) -> io::Result<()> {
+        epoll.add(&self.as_raw_fd(), token, interest, opts)
+    }
+
+    fn modify(
+        &self,
+        epoll: &Epoll,
+        token: Token,
+        interest: Ready,
+        opts: EpollOpt,
+    ) -> io::Result<()> {
+        epoll.modify(&self.as_raw_fd(), token, interest, opts)
+    }
+
+    fn delete(&self, epoll: &Epoll) -> io::Result<()> {
+        epoll.delete(&self.as_raw_fd())
+    }
+}
+
+#[derive(Debug)]
+pub struct ChildStdin {
+    inner: FileDesc,
+}
+
+impl ChildStdin {
+    fn new(stdin: process::ChildStdin) -> io::Result<ChildStdin> {
+        let inner = unsafe { FileDesc::new(stdin.into_raw_fd()) };
+        inner.set_nonblocking(true)?;
+
+        Ok(ChildStdin { inner })
+    }
+}
+
+impl Write for ChildStdin {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl Write for &ChildStdin {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        (&self.inner).write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        (&self.inner).flush()
+    }
+}
+
+impl AsRawFd for ChildStdin {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+impl IntoRawFd for ChildStdin {
+    fn into_raw_fd(self) -> RawFd {
+        self.inner.into_raw_fd()
+    }
+}
+
+impl Source for ChildStdin {
+    fn add(&self, epoll: &Epoll, token: Token, interest: Ready, opts: EpollOpt) -> io::Result<()> {
+        epoll.add(&self.as_raw_fd(), token, interest, opts)
+    }
+
+    fn modify(
+        &self,
+        epoll: &Epoll,
+        token: Token,
+        interest: Ready,
+        opts: EpollOpt,
+    ) -> io::Result<()> {
+        epoll.modify(&self.as_raw_fd(), token, interest, opts)
+    }
+
+    fn delete(&self, epoll: &Epoll) -> io::Result<()> {
+        epoll.delete(&self.as_raw_fd())
+    }
+}
+
+#[derive(Debug)]
+pub struct ChildStdout {
+    inner: FileDesc,
+}
+
+impl ChildStdout {
+    fn new(stdout: process::ChildStdout) -> io::Result<ChildStdout> {
+        let inner = unsafe { FileDesc::new(stdout.into_raw_fd()) };
+        inner.set_nonblocking(true)?;
+
+        Ok(ChildStdout { inner })
+    }
+}
+
+impl Read for ChildStdout {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl Read for &ChildStdout {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        (&self.inner).read(buf)
+    }
+}
+
+impl AsRawFd for ChildStdout {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+impl IntoRawFd for ChildStdout {
+    fn into_raw_fd(self) -> RawFd {
+        self.inner.into_raw_fd()
+    }
+}
+
+impl Source for ChildStdout {
+    fn add(&self, epoll: &Epoll, token: Token, interest: Ready, opts: EpollOpt) -> io::Result<()> {
+        epoll.add(&self.as_raw_fd(), token, interest, opts)
+    }
+
+    fn modify(
+        &self,
+        epoll: &Epoll,
+        token: Token,
+        interest: Ready,
+        opts: EpollOpt,
+    ) -> io::Result<()> {
+        epoll.modify(&self.as_raw_fd(), token, interest, opts)
+    }
+
+    fn delete(&self, epoll: &Epoll) -> io::Result<()> {
+        epoll.delete(&self.as_raw_fd())
+    }
+}
+
+#[derive(Debug)]
+pub struct ChildStderr {
+    inner: FileDesc,
+}
+
+impl ChildStderr {
+    fn new(stderr: process::ChildStderr) -> io::Result<ChildStderr> {
+        let inner = unsafe { FileDesc::new(stderr.into_raw_fd()) };
+        inner.set_nonblocking(true)?;
+
+        Ok(ChildStderr { inner })
+    }
+}
+
+impl Read for ChildStderr {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl Read for &ChildStderr {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        (&self.inner).read(buf)
+    }
+}
+
+impl AsRawFd for ChildStderr {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+impl IntoRawFd for ChildStderr {
+    fn into_raw_fd(self) -> RawFd {
+        self.inner.into_raw_fd()
+    }
+}
+
+impl Source for ChildStderr {
+    fn add(&self, epoll: &Epoll, token: Token, interest: Ready, opts: EpollOpt) -> io::Result<()> {
+        epoll.add(&self.as_raw_fd(), token, interest, opts)
+    }
+
+    fn modify(
+        &self,
+        epoll: &Epoll,
+        token: Token,
+        interest: Ready,
+        opts: EpollOpt,
+    ) -> io::Result<()> {
+        epoll.modify(&self.as_raw_fd(), token, interest, opts)
+    }
+
+    fn delete(&self, epoll: &Epoll) -> io::Result<()> {
+        epoll.delete(&self.as_raw_fd())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::io::{self, ErrorKind, Read, Write};
+    use std::os::unix::io::AsRawFd;
+    use std::process;
+    use std::time::{Duration, Instant};
+
+    use crate::epoll::{Epoll, EpollOpt, Events, Ready, Token};
+    use crate::poll;
+    use crate::sys::pipe;
+
+    use super::Command;
+
+    #[test]
+    fn stream_stdout_and_exit() {
+        let epoll = Epoll::new().unwrap();
+        let mut events = Events::with_capacity(8);
+
+        let mut child = Command::new("cat").spawn().unwrap();
+
+        let stdout = child.stdout.take().unwrap();
+        epoll.add(&stdout, Token(1), Ready::readable(), EpollOpt::level()).unwrap();
+        epoll.add(&child, Token(2), Ready::readable(), EpollOpt::level()).unwrap();
+
+        let mut stdin = child.stdin.take().unwrap();
+        stdin.write_all(b"hello").unwrap();
+        drop(stdin);
+
+        let mut output = Vec::new();
+        let mut eof = false;
+        let mut exited = false;
+
+        while !(eof && exited) {
+            epoll.wait(&mut events, Some(Duration::from_secs(5))).unwrap();
+            assert!(!events.is_empty());
+
+            for event in &events {
+                match event.token() {
+                    Token(1) => {
+                        let mut buf = [0u8; 64];
+                        loop {
+                            match (&stdout).read(&mut buf) {
+                                Ok(0) => {
+                                    epoll.delete(&stdout).unwrap();
+                                    eof = true;
+                                    break;
+                                }
+                                Ok(n) => output.extend_from_slice(&buf[..n]),
+                                Err(ref e) if e.kind() == ErrorKind::WouldBlock => break,
+                                Err(e) => panic!("{:?}", e),
+                            }
+                        }
+                    }
+                    Token(2) => exited = true,
+                    _ => unreachable!(),
+                }
+            }
+        }
+
+        assert_eq!(output, b"hello");
+        assert!(child.try_wait().unwrap().unwrap().success());
+    }
+
+    #[test]
+    fn sigchld_fallback() {
+        // the SIGCHLD handler would interrupt the epoll waits of other tests
+        if env::var_os("QUEEN_IO_TEST_ISOLATED").is_none() {
+            let output = process::Command::new(env::current_exe().unwrap())
+                .args(["--exact", "process::test::sigchld_fallback"])
+                .env("QUEEN_IO_TEST_ISOLATED", "1")
+                .output()
+                .unwrap();
+
+            assert!(output.status.success(), "{:?}", output);
+            return;
+        }
+
+        let epoll = Epoll::new().unwrap();
+        let mut events = Events::with_capacity(8);
+
+        let mut child = Command::new("sleep")
+            .arg("0.1")
+            .spawn_with(|_| Err(io::Error::from_raw_os_error(libc::ENOSYS)))
+            .unwrap();
+
+        epoll.add(&child, Token(1), Ready::readable(), EpollOpt::level()).unwrap();
+
+        // any SIGCHLD wakes up the child, until it has exited
+        let status = loop {
+            match epoll.wait(&mut events, Some(Duration::from_secs(5))) {
+                Ok(_) => assert!(!events.is_empty()),
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => panic!("{:?}", e),
+            }
+
+            if let Some(status) = child.try_wait().unwrap() {
+                break status;
+            }
+        };
+
+        assert!(status.success());
+
+        // a child not spawned by this crate does not cut poll::wait short
+        let (reader, _writer) = pipe::pipe().unwrap();
+        let mut other = process::Command::new("sleep").arg("0.05").spawn().unwrap();
+
+        let start = Instant::now();
+        let timeout = Some(Duration::from_millis(300));
+        let ready = poll::wait(reader.as_raw_fd(), poll::Ready::readable(), timeout).unwrap();
+        assert!(ready.is_empty());
+        assert!(start.elapsed() >= Duration::from_millis(300));
+
+        assert!(other.wait().unwrap().success());
+    }
+}
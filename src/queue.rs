@@ -1,10 +1,14 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
 use std::io;
 use std::os::unix::io::{AsRawFd, RawFd};
 use std::sync::atomic::AtomicUsize;
 use std::sync::atomic::Ordering::{AcqRel, Acquire, Relaxed};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
 
 use crate::epoll::{Epoll, EpollOpt, Ready, Source, Token};
+use crate::sys::timerfd::TimerFd;
 use crate::waker::Waker;
 
 pub use concurrent_queue::{ConcurrentQueue, PopError, PushError};
@@ -149,3 +153,241 @@ impl<T: Send> Source for Queue<T> {
         self.inner.waker.delete(epoll)
     }
 }
+
+/// A queue whose items become poppable only once their delay has elapsed.
+///
+/// The queue is readable while at least one item is due, it drives a
+/// `TimerFd` armed to the nearest deadline. Like `Queue`, it can be cloned and
+/// shared between threads.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use queen_io::epoll::{Epoll, Events, Token, Ready, EpollOpt};
+/// use queen_io::queue::DelayQueue;
+///
+/// let epoll = Epoll::new().unwrap();
+/// let mut events = Events::with_capacity(8);
+///
+/// let queue = DelayQueue::new().unwrap();
+/// epoll.add(&queue, Token(0), Ready::readable(), EpollOpt::level()).unwrap();
+///
+/// queue.push("retry", Duration::from_millis(10)).unwrap();
+/// assert_eq!(queue.pop().unwrap(), None);
+///
+/// epoll.wait(&mut events, None).unwrap();
+/// assert_eq!(queue.pop().unwrap(), Some("retry"));
+/// ```
+pub struct DelayQueue<T> {
+    inner: Arc<DelayQueueInner<T>>,
+}
+
+struct DelayQueueInner<T> {
+    state: Mutex<DelayState<T>>,
+    timerfd: TimerFd,
+}
+
+struct DelayState<T> {
+    heap: BinaryHeap<Delayed<T>>,
+    seq: u64,
+}
+
+struct Delayed<T> {
+    deadline: Instant,
+    seq: u64,
+    value: T,
+}
+
+impl<T> PartialEq for Delayed<T> {
+    fn eq(&self, other: &Delayed<T>) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<T> Eq for Delayed<T> {}
+
+impl<T> PartialOrd for Delayed<T> {
+    fn partial_cmp(&self, other: &Delayed<T>) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T> Ord for Delayed<T> {
+    // reversed, the earliest deadline is on top of the heap, ties in push order
+    fn cmp(&self, other: &Delayed<T>) -> Ordering {
+        (other.deadline, other.seq).cmp(&(self.deadline, self.seq))
+    }
+}
+
+impl<T: Send> DelayQueue<T> {
+    pub fn new() -> io::Result<DelayQueue<T>> {
+        Ok(DelayQueue {
+            inner: Arc::new(DelayQueueInner {
+                state: Mutex::new(DelayState {
+                    heap: BinaryHeap::new(),
+                    seq: 0,
+                }),
+                timerfd: TimerFd::new()?,
+            }),
+        })
+    }
+
+    /// Push an item that becomes poppable after `delay`.
+    pub fn push(&self, value: T, delay: Duration) -> io::Result<()> {
+        self.push_at(value, Instant::now() + delay)
+    }
+
+    /// Push an item that becomes poppable at `deadline`.
+    ///
+    /// If the timer fd cannot be armed, the item is not queued and the error
+    /// is returned.
+    pub fn push_at(&self, value: T, deadline: Instant) -> io::Result<()> {
+        let mut state = self.lock();
+
+        let seq = state.seq;
+        state.seq += 1;
+
+        let earliest = state
+            .heap
+            .peek()
+            .map(|head| deadline < head.deadline)
+            .unwrap_or(true);
+
+        // arm first, so that the item is not queued if that fails
+        if earliest {
+            self.inner.timerfd.set_at(deadline)?;
+        }
+
+        state.heap.push(Delayed {
+            deadline,
+            seq,
+            value,
+        });
+
+        Ok(())
+    }
+
+    /// Pop the item with the earliest deadline if it is due, `None` if no
+    /// item is due yet.
+    ///
+    /// The timer fd is then re-armed to the next deadline. If that fails,
+    /// the item is left in the queue and the error is returned.
+    pub fn pop(&self) -> io::Result<Option<T>> {
+        let mut state = self.lock();
+
+        let item = match state.heap.peek() {
+            Some(head) if head.deadline <= Instant::now() => state.heap.pop().unwrap(),
+            _ => return Ok(None),
+        };
+
+        let res = match state.heap.peek() {
+            Some(head) => self.inner.timerfd.set_at(head.deadline),
+            None => self.inner.timerfd.disarm(),
+        };
+
+        if let Err(err) = res {
+            state.heap.push(item);
+            return Err(err);
+        }
+
+        Ok(Some(item.value))
+    }
+
+    /// The deadline of the next item.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.lock().heap.peek().map(|head| head.deadline)
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock().heap.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lock().heap.is_empty()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, DelayState<T>> {
+        self.inner
+            .state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl<T: Send> Clone for DelayQueue<T> {
+    fn clone(&self) -> DelayQueue<T> {
+        DelayQueue {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T: Send> AsRawFd for DelayQueue<T> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.timerfd.as_raw_fd()
+    }
+}
+
+impl<T: Send> Source for DelayQueue<T> {
+    fn add(&self, epoll: &Epoll, token: Token, interest: Ready, opts: EpollOpt) -> io::Result<()> {
+        self.inner.timerfd.add(epoll, token, interest, opts)
+    }
+
+    fn modify(
+        &self,
+        epoll: &Epoll,
+        token: Token,
+        interest: Ready,
+        opts: EpollOpt,
+    ) -> io::Result<()> {
+        self.inner.timerfd.modify(epoll, token, interest, opts)
+    }
+
+    fn delete(&self, epoll: &Epoll) -> io::Result<()> {
+        self.inner.timerfd.delete(epoll)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::os::unix::io::AsRawFd;
+    use std::time::{Duration, Instant};
+
+    use crate::poll;
+
+    use super::DelayQueue;
+
+    fn is_readable(queue: &DelayQueue<&str>, timeout: Duration) -> bool {
+        poll::wait(queue.as_raw_fd(), poll::Ready::readable(), Some(timeout))
+            .unwrap()
+            .is_readable()
+    }
+
+    #[test]
+    fn delay_queue_readable_at_earliest_deadline() {
+        let queue = DelayQueue::new().unwrap();
+        let start = Instant::now();
+
+        queue.push("b", Duration::from_millis(100)).unwrap();
+        queue.push("a", Duration::from_millis(50)).unwrap();
+        assert_eq!(queue.len(), 2);
+
+        assert!(!is_readable(&queue, Duration::from_millis(10)));
+        assert_eq!(queue.pop().unwrap(), None);
+
+        assert!(is_readable(&queue, Duration::from_secs(5)));
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        assert_eq!(queue.pop().unwrap(), Some("a"));
+        assert_eq!(queue.pop().unwrap(), None);
+
+        // re-armed to the next deadline
+        assert!(!is_readable(&queue, Duration::from_millis(10)));
+
+        assert!(is_readable(&queue, Duration::from_secs(5)));
+        assert!(start.elapsed() >= Duration::from_millis(100));
+        assert_eq!(queue.pop().unwrap(), Some("b"));
+        assert!(queue.is_empty());
+        assert_eq!(queue.next_deadline(), None);
+    }
+}
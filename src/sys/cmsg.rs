@@ -0,0 +1,212 @@
+use std::mem;
+use std::ptr;
+
+use libc::{self, c_int, c_uint};
+
+/// The bytes a control message with `len` bytes of data takes in a buffer,
+/// header and padding included.
+/// view: `<http://man7.org/linux/man-pages/man3/cmsg.3.html>`
+pub fn space(len: usize) -> usize {
+    unsafe { libc::CMSG_SPACE(len as c_uint) as usize }
+}
+
+fn header_len() -> usize {
+    unsafe { libc::CMSG_LEN(0) as usize }
+}
+
+/// Plain data carried by control messages.
+///
+/// # Safety
+///
+/// `Self` has no padding bytes, and every pattern of `size_of::<Self>()`
+/// bytes is a valid `Self`.
+pub(crate) unsafe trait CmsgData: Copy {}
+
+unsafe impl CmsgData for u16 {}
+unsafe impl CmsgData for c_int {}
+unsafe impl CmsgData for libc::ucred {}
+unsafe impl CmsgData for libc::in_pktinfo {}
+unsafe impl CmsgData for libc::in6_pktinfo {}
+unsafe impl CmsgData for libc::timespec {}
+unsafe impl CmsgData for [libc::timespec; 3] {}
+
+/// A buffer for the ancillary data of sendmsg(2) and recvmsg(2), aligned
+/// for `cmsghdr`.
+#[derive(Debug, Clone, Default)]
+pub struct CmsgBuf {
+    buf: Vec<u64>,
+    len: usize,
+}
+
+impl CmsgBuf {
+    /// Create an empty buffer holding at least `capacity` bytes, use `space`
+    /// to size it.
+    pub fn with_capacity(capacity: usize) -> CmsgBuf {
+        CmsgBuf {
+            buf: vec![0; capacity.div_ceil(mem::size_of::<u64>())],
+            len: 0,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.buf.len() * mem::size_of::<u64>()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    /// Append a control message.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the buffer has no room for `space(data.len())` more bytes.
+    pub fn push(&mut self, level: c_int, ty: c_int, data: &[u8]) {
+        let space = space(data.len());
+        assert!(
+            self.len + space <= self.capacity(),
+            "control message buffer is too small"
+        );
+
+        unsafe {
+            let base = (self.buf.as_mut_ptr() as *mut u8).add(self.len);
+            ptr::write_bytes(base, 0, space);
+
+            let mut cmsg: libc::cmsghdr = mem::zeroed();
+            cmsg.cmsg_len = libc::CMSG_LEN(data.len() as c_uint) as _;
+            cmsg.cmsg_level = level;
+            cmsg.cmsg_type = ty;
+
+            ptr::write_unaligned(base as *mut libc::cmsghdr, cmsg);
+            ptr::copy_nonoverlapping(data.as_ptr(), base.add(header_len()), data.len());
+        }
+
+        self.len += space;
+    }
+
+    /// Append a control message holding `value`.
+    pub(crate) fn push_value<T: CmsgData>(&mut self, level: c_int, ty: c_int, value: &T) {
+        let data = unsafe {
+            std::slice::from_raw_parts(value as *const T as *const u8, mem::size_of::<T>())
+        };
+
+        self.push(level, ty, data)
+    }
+
+    /// The pointer for the `msg_control` of sendmsg(2), null if the buffer
+    /// is empty.
+    pub fn as_ptr(&self) -> *const libc::c_void {
+        if self.buf.is_empty() {
+            ptr::null()
+        } else {
+            self.buf.as_ptr() as *const libc::c_void
+        }
+    }
+
+    /// The pointer for the `msg_control` of recvmsg(2), null if the buffer
+    /// has no capacity.
+    pub fn as_mut_ptr(&mut self) -> *mut libc::c_void {
+        if self.buf.is_empty() {
+            ptr::null_mut()
+        } else {
+            self.buf.as_mut_ptr() as *mut libc::c_void
+        }
+    }
+
+    /// Set the length to the `msg_controllen` returned by recvmsg(2).
+    pub fn set_len(&mut self, len: usize) {
+        self.len = len.min(self.capacity());
+    }
+
+    /// Iterate the control messages in the buffer.
+    pub fn iter(&self) -> Cmsgs<'_> {
+        let bytes = unsafe { std::slice::from_raw_parts(self.buf.as_ptr() as *const u8, self.len) };
+
+        Cmsgs { bytes }
+    }
+}
+
+/// A control message of a `CmsgBuf`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cmsg<'a> {
+    pub level: c_int,
+    pub ty: c_int,
+    pub data: &'a [u8],
+}
+
+impl Cmsg<'_> {
+    /// Read the data as a `T`, `None` if it is too short.
+    pub(crate) fn read<T: CmsgData>(&self) -> Option<T> {
+        if self.data.len() < mem::size_of::<T>() {
+            return None;
+        }
+
+        Some(unsafe { ptr::read_unaligned(self.data.as_ptr() as *const T) })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Cmsgs<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Iterator for Cmsgs<'a> {
+    type Item = Cmsg<'a>;
+
+    fn next(&mut self) -> Option<Cmsg<'a>> {
+        if self.bytes.len() < header_len() {
+            return None;
+        }
+
+        let cmsg: libc::cmsghdr =
+            unsafe { ptr::read_unaligned(self.bytes.as_ptr() as *const libc::cmsghdr) };
+
+        let len = cmsg.cmsg_len as usize;
+
+        if len < header_len() || len > self.bytes.len() {
+            self.bytes = &[];
+            return None;
+        }
+
+        let data = &self.bytes[header_len()..len];
+        let next = space(len - header_len()).min(self.bytes.len());
+        self.bytes = &self.bytes[next..];
+
+        Some(Cmsg {
+            level: cmsg.cmsg_level,
+            ty: cmsg.cmsg_type,
+            data,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{space, CmsgBuf};
+
+    #[test]
+    fn push_iter() {
+        let mut buf = CmsgBuf::with_capacity(space(3) + space(4));
+
+        buf.push(1, 2, b"abc");
+        buf.push_value(3, 4, &7i32);
+        assert_eq!(buf.len(), space(3) + space(4));
+
+        let cmsgs: Vec<_> = buf.iter().collect();
+        assert_eq!(cmsgs.len(), 2);
+        assert_eq!(
+            (cmsgs[0].level, cmsgs[0].ty, cmsgs[0].data),
+            (1, 2, &b"abc"[..])
+        );
+        assert_eq!(cmsgs[1].read::<i32>(), Some(7));
+        assert!(cmsgs[1].read::<libc::ucred>().is_none());
+    }
+}
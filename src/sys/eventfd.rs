@@ -1,9 +1,12 @@
 use std::os::unix::io::{RawFd, AsRawFd, FromRawFd, IntoRawFd};
 use std::io::{self, Read, Write};
+use std::time::{Duration, Instant};
+use std::{error, fmt, ops};
 
 use libc;
 
 use crate::epoll::{Epoll, Token, Ready, EpollOpt, Source};
+use crate::poll;
 
 use super::fd::FileDesc;
 
@@ -11,6 +14,170 @@ pub const EFD_CLOEXEC: i32 = libc::EFD_CLOEXEC;
 pub const EFD_NONBLOCK: i32 = libc::EFD_NONBLOCK;
 pub const EFD_SEMAPHORE: i32 = libc::EFD_SEMAPHORE;
 
+/// Typed flags for `eventfd(2)`, combine them with `|`.
+#[derive(Copy, PartialEq, Eq, Clone, PartialOrd, Ord)]
+pub struct EfdFlags(i32);
+
+impl EfdFlags {
+    #[inline]
+    pub fn empty() -> EfdFlags {
+        EfdFlags(0)
+    }
+
+    #[inline]
+    pub fn cloexec() -> EfdFlags {
+        EfdFlags(EFD_CLOEXEC)
+    }
+
+    #[inline]
+    pub fn nonblock() -> EfdFlags {
+        EfdFlags(EFD_NONBLOCK)
+    }
+
+    #[inline]
+    pub fn semaphore() -> EfdFlags {
+        EfdFlags(EFD_SEMAPHORE)
+    }
+
+    #[inline]
+    pub fn is_cloexec(self) -> bool {
+        self.contains(EfdFlags::cloexec())
+    }
+
+    #[inline]
+    pub fn is_nonblock(self) -> bool {
+        self.contains(EfdFlags::nonblock())
+    }
+
+    #[inline]
+    pub fn is_semaphore(self) -> bool {
+        self.contains(EfdFlags::semaphore())
+    }
+
+    #[inline]
+    pub fn contains(self, other: EfdFlags) -> bool {
+        (self & other) == other
+    }
+
+    #[inline]
+    pub fn insert(&mut self, other: EfdFlags) {
+        self.0 |= other.0;
+    }
+
+    #[inline]
+    pub fn remove(&mut self, other: EfdFlags) {
+        self.0 &= !other.0;
+    }
+
+    #[inline]
+    pub fn as_i32(self) -> i32 {
+        self.0
+    }
+}
+
+impl Default for EfdFlags {
+    /// EFD_CLOEXEC | EFD_NONBLOCK
+    fn default() -> EfdFlags {
+        EfdFlags::cloexec() | EfdFlags::nonblock()
+    }
+}
+
+impl ops::BitOr for EfdFlags {
+    type Output = EfdFlags;
+
+    #[inline]
+    fn bitor(self, other: EfdFlags) -> EfdFlags {
+        EfdFlags(self.0 | other.0)
+    }
+}
+
+impl ops::BitAnd for EfdFlags {
+    type Output = EfdFlags;
+
+    #[inline]
+    fn bitand(self, other: EfdFlags) -> EfdFlags {
+        EfdFlags(self.0 & other.0)
+    }
+}
+
+impl ops::Sub for EfdFlags {
+    type Output = EfdFlags;
+
+    #[inline]
+    fn sub(self, other: EfdFlags) -> EfdFlags {
+        EfdFlags(self.0 & !other.0)
+    }
+}
+
+impl fmt::Debug for EfdFlags {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        let mut one = false;
+        let flags = [
+            (EfdFlags::cloexec(), "Cloexec"),
+            (EfdFlags::nonblock(), "Nonblock"),
+            (EfdFlags::semaphore(), "Semaphore")];
+
+        write!(fmt, "EfdFlags {{")?;
+
+        for &(flag, msg) in &flags {
+            if self.contains(flag) {
+                if one { write!(fmt, " | ")? }
+                write!(fmt, "{}", msg)?;
+
+                one = true
+            }
+        }
+
+        write!(fmt, "}}")?;
+
+        Ok(())
+    }
+}
+
+/// The error inside the `io::Error` returned by a non-blocking write that
+/// would overflow the eventfd counter, check it with `is_overflow`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CounterOverflow;
+
+impl fmt::Display for CounterOverflow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "eventfd counter overflow")
+    }
+}
+
+impl error::Error for CounterOverflow {}
+
+/// Whether `err` was returned because the eventfd counter would overflow.
+pub fn is_overflow(err: &io::Error) -> bool {
+    err.get_ref()
+        .map(|inner| inner.is::<CounterOverflow>())
+        .unwrap_or(false)
+}
+
+fn read_counter(fd: &FileDesc) -> io::Result<u64> {
+    let mut buf = [0u8; 8];
+    (&*fd).read_exact(&mut buf)?;
+    Ok(u64::from_ne_bytes(buf))
+}
+
+fn write_counter(fd: &FileDesc, val: u64) -> io::Result<()> {
+    if val == u64::MAX {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "eventfd value must be less than u64::MAX"
+        ));
+    }
+
+    let buf: [u8; 8] = val.to_ne_bytes();
+
+    match (&*fd).write_all(&buf) {
+        Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+            Err(io::Error::other(CounterOverflow))
+        }
+        other => other,
+    }
+}
+
 #[derive(Debug)]
 pub struct EventFd {
     inner: FileDesc
@@ -24,6 +191,19 @@ impl EventFd {
         EventFd::with_options(0, flags)
     }
 
+    /// Create an eventfd with typed flags
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use queen_io::sys::eventfd::{EventFd, EfdFlags};
+    ///
+    /// let eventfd = EventFd::with_flags(0, EfdFlags::cloexec() | EfdFlags::nonblock());
+    /// ```
+    pub fn with_flags(initval: u32, flags: EfdFlags) -> io::Result<EventFd> {
+        EventFd::with_options(initval, flags.as_i32())
+    }
+
     pub fn with_options(initval: u32, flags: i32) -> io::Result<EventFd> {
         let eventfd = syscall!(eventfd(initval, flags))?;
         Ok(EventFd {
@@ -36,16 +216,128 @@ impl EventFd {
     }
 
     pub fn read(&self) -> io::Result<u64> {
-        let mut buf = [0u8; 8];
-        (&self.inner).read_exact(&mut buf)?;
-        let temp: u64 = u64::from_ne_bytes(buf);
-        Ok(temp)
+        read_counter(&self.inner)
     }
 
+    /// Add `val` to the counter. If the counter would exceed `u64::MAX - 1`, a
+    /// non-blocking eventfd returns an error for which `is_overflow` is true,
+    /// the write can be retried once the eventfd is writable again.
     pub fn write(&self, val: u64) -> io::Result<()> {
-        let buf: [u8; 8] = val.to_ne_bytes();
-        (&self.inner).write_all(&buf)?;
-        Ok(())
+        write_counter(&self.inner, val)
+    }
+}
+
+/// A counting semaphore on an eventfd created with EFD_SEMAPHORE, shareable
+/// between threads and pollable: it is readable while a permit is available.
+#[derive(Debug)]
+pub struct EventFdSemaphore {
+    inner: FileDesc
+}
+
+impl EventFdSemaphore {
+    /// Create a semaphore with `permits` available permits and flags:
+    /// EFD_CLOEXEC | EFD_NONBLOCK | EFD_SEMAPHORE
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use queen_io::sys::eventfd::EventFdSemaphore;
+    ///
+    /// let sem = EventFdSemaphore::new(1).unwrap();
+    /// assert!(sem.try_acquire().unwrap());
+    /// assert!(!sem.try_acquire().unwrap());
+    /// sem.release(1).unwrap();
+    /// ```
+    pub fn new(permits: u32) -> io::Result<EventFdSemaphore> {
+        let flags = EfdFlags::default() | EfdFlags::semaphore();
+        let eventfd = syscall!(eventfd(permits, flags.as_i32()))?;
+        Ok(EventFdSemaphore {
+            inner: unsafe { FileDesc::new(eventfd) }
+        })
+    }
+
+    pub fn try_clone(&self) -> io::Result<EventFdSemaphore> {
+        self.inner.try_clone().map(|fd| EventFdSemaphore { inner: fd })
+    }
+
+    /// Take one permit if one is available, without blocking.
+    pub fn try_acquire(&self) -> io::Result<bool> {
+        match read_counter(&self.inner) {
+            Ok(_) => Ok(true),
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => Ok(false),
+            Err(e) => Err(e)
+        }
+    }
+
+    /// Take one permit, blocking the current thread until one is available.
+    pub fn acquire(&self) -> io::Result<()> {
+        self.acquire_timeout(None).map(|_| ())
+    }
+
+    /// Take one permit, blocking the current thread for at most `timeout`.
+    /// Returns false if no permit became available in time.
+    pub fn acquire_timeout(&self, timeout: Option<Duration>) -> io::Result<bool> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+
+        loop {
+            // another thread may take the permit between poll and read
+            if self.try_acquire()? {
+                return Ok(true)
+            }
+
+            let timeout = match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Ok(false)
+                    }
+                    Some(deadline - now)
+                }
+                None => None
+            };
+
+            poll::wait(self.as_raw_fd(), poll::Ready::readable(), timeout)?;
+        }
+    }
+
+    /// Return `permits` permits, fails with `is_overflow` if the count would
+    /// exceed `u64::MAX - 1`.
+    pub fn release(&self, permits: u64) -> io::Result<()> {
+        write_counter(&self.inner, permits)
+    }
+}
+
+impl FromRawFd for EventFdSemaphore {
+    unsafe fn from_raw_fd(fd: RawFd) -> Self {
+        EventFdSemaphore {
+            inner: FileDesc::new(fd)
+        }
+    }
+}
+
+impl IntoRawFd for EventFdSemaphore {
+    fn into_raw_fd(self) -> RawFd {
+        self.inner.into_raw_fd()
+    }
+}
+
+impl AsRawFd for EventFdSemaphore {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+impl Source for EventFdSemaphore {
+    fn add(&self, epoll: &Epoll, token: Token, interest: Ready, opts: EpollOpt) -> io::Result<()> {
+        epoll.add(&self.as_raw_fd(), token, interest, opts)
+    }
+
+    fn modify(&self, epoll: &Epoll, token: Token, interest: Ready, opts: EpollOpt) -> io::Result<()> {
+        epoll.modify(&self.as_raw_fd(), token, interest, opts)
+    }
+
+    fn delete(&self, epoll: &Epoll) -> io::Result<()> {
+        epoll.delete(&self.as_raw_fd())
     }
 }
 
@@ -85,7 +377,11 @@ impl Source for EventFd {
 
 #[cfg(test)]
 mod test {
-    use super::EventFd;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    use super::{is_overflow, EventFd, EventFdSemaphore};
 
     #[test]
     fn write_and_read() {
@@ -100,6 +396,33 @@ mod test {
         let eventfd = EventFd::new().unwrap();
 
         assert!(eventfd.write(0xfffffffffffffffe).is_ok());
-        assert!(eventfd.write(0xfffffffffffffffe).is_err()); // Err(Os { code: 11, kind: WouldBlock, message: "Resource temporarily unavailable" })
+        assert!(eventfd.write(0xfffffffffffffffe).is_err()); // Err(Custom { kind: Other, error: CounterOverflow })
+    }
+
+    #[test]
+    fn write_overflow() {
+        let eventfd = EventFd::new().unwrap();
+
+        eventfd.write(0xfffffffffffffffe).unwrap();
+        assert!(is_overflow(&eventfd.write(1).unwrap_err()));
+        assert!(!is_overflow(&eventfd.write(u64::MAX).unwrap_err()));
+    }
+
+    #[test]
+    fn semaphore() {
+        let sem = Arc::new(EventFdSemaphore::new(2).unwrap());
+
+        assert!(sem.try_acquire().unwrap());
+        assert!(sem.try_acquire().unwrap());
+        assert!(!sem.try_acquire().unwrap());
+        assert!(!sem.acquire_timeout(Some(Duration::from_millis(10))).unwrap());
+
+        let sem2 = sem.clone();
+        let handle = thread::spawn(move || sem2.acquire().unwrap());
+
+        sem.release(1).unwrap();
+        handle.join().unwrap();
+
+        assert!(!sem.try_acquire().unwrap());
     }
 }
@@ -17,6 +17,34 @@ impl FileDesc {
     pub fn try_clone(&self) -> io::Result<FileDesc> {
         Ok(FileDesc(self.0.try_clone()?))
     }
+
+    pub fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        let fd = self.as_raw_fd();
+        let flags = syscall!(fcntl(fd, libc::F_GETFL))?;
+
+        let new_flags = if nonblocking {
+            flags | libc::O_NONBLOCK
+        } else {
+            flags & !libc::O_NONBLOCK
+        };
+
+        if new_flags != flags {
+            syscall!(fcntl(fd, libc::F_SETFL, new_flags))?;
+        }
+
+        Ok(())
+    }
+
+    pub fn set_cloexec(&self) -> io::Result<()> {
+        let fd = self.as_raw_fd();
+        let flags = syscall!(fcntl(fd, libc::F_GETFD))?;
+
+        if flags & libc::FD_CLOEXEC == 0 {
+            syscall!(fcntl(fd, libc::F_SETFD, flags | libc::FD_CLOEXEC))?;
+        }
+
+        Ok(())
+    }
 }
 
 impl FromRawFd for FileDesc {
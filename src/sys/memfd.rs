@@ -0,0 +1,468 @@
+use std::ffi::CString;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::os::unix::fs::FileExt;
+use std::os::unix::io::{AsRawFd, FromRawFd, IntoRawFd, RawFd};
+use std::{fmt, mem, ops, ptr, slice};
+
+use libc;
+
+use super::fd::FileDesc;
+
+pub const MFD_CLOEXEC: u32 = libc::MFD_CLOEXEC;
+pub const MFD_ALLOW_SEALING: u32 = libc::MFD_ALLOW_SEALING;
+
+/// File seals, view: `<http://man7.org/linux/man-pages/man2/fcntl.2.html>`
+#[derive(Copy, PartialEq, Eq, Clone, PartialOrd, Ord)]
+pub struct Seals(u32);
+
+const SEAL: u32 = libc::F_SEAL_SEAL as u32;
+const SHRINK: u32 = libc::F_SEAL_SHRINK as u32;
+const GROW: u32 = libc::F_SEAL_GROW as u32;
+const WRITE: u32 = libc::F_SEAL_WRITE as u32;
+
+impl Seals {
+    #[inline]
+    pub fn empty() -> Seals {
+        Seals(0)
+    }
+
+    /// No more seals can be added.
+    #[inline]
+    pub fn seal() -> Seals {
+        Seals(SEAL)
+    }
+
+    /// The file size can not be reduced.
+    #[inline]
+    pub fn shrink() -> Seals {
+        Seals(SHRINK)
+    }
+
+    /// The file size can not be increased.
+    #[inline]
+    pub fn grow() -> Seals {
+        Seals(GROW)
+    }
+
+    /// The file contents can not be modified.
+    #[inline]
+    pub fn write() -> Seals {
+        Seals(WRITE)
+    }
+
+    /// All of shrink, grow and write, the file is immutable.
+    #[inline]
+    pub fn immutable() -> Seals {
+        Seals(SHRINK | GROW | WRITE)
+    }
+
+    #[inline]
+    pub fn is_empty(self) -> bool {
+        self == Seals::empty()
+    }
+
+    #[inline]
+    pub fn contains(self, other: Seals) -> bool {
+        (self & other) == other
+    }
+
+    #[inline]
+    pub fn insert(&mut self, other: Seals) {
+        self.0 |= other.0;
+    }
+
+    #[inline]
+    pub fn remove(&mut self, other: Seals) {
+        self.0 &= !other.0;
+    }
+
+    #[inline]
+    pub fn as_u32(self) -> u32 {
+        self.0
+    }
+}
+
+impl ops::BitOr for Seals {
+    type Output = Seals;
+
+    #[inline]
+    fn bitor(self, other: Seals) -> Seals {
+        Seals(self.0 | other.0)
+    }
+}
+
+impl ops::BitAnd for Seals {
+    type Output = Seals;
+
+    #[inline]
+    fn bitand(self, other: Seals) -> Seals {
+        Seals(self.0 & other.0)
+    }
+}
+
+impl ops::Sub for Seals {
+    type Output = Seals;
+
+    #[inline]
+    fn sub(self, other: Seals) -> Seals {
+        Seals(self.0 & !other.0)
+    }
+}
+
+impl From<u32> for Seals {
+    fn from(seals: u32) -> Seals {
+        Seals(seals)
+    }
+}
+
+impl fmt::Debug for Seals {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        let mut one = false;
+        let flags = [
+            (Seals::seal(), "Seal"),
+            (Seals::shrink(), "Shrink"),
+            (Seals::grow(), "Grow"),
+            (Seals::write(), "Write"),
+        ];
+
+        write!(fmt, "Seals {{")?;
+
+        for &(flag, msg) in &flags {
+            if self.contains(flag) {
+                if one {
+                    write!(fmt, " | ")?
+                }
+                write!(fmt, "{}", msg)?;
+
+                one = true
+            }
+        }
+
+        write!(fmt, "}}")?;
+
+        Ok(())
+    }
+}
+
+/// An anonymous memory backed file, which can be passed to other processes
+/// by fd. With seals, the receiver can verify that the contents will not
+/// change under it.
+#[derive(Debug)]
+pub struct MemFd {
+    inner: FileDesc,
+}
+
+impl MemFd {
+    /// Create a memfd with flags: MFD_CLOEXEC | MFD_ALLOW_SEALING
+    /// view: `<http://man7.org/linux/man-pages/man2/memfd_create.2.html>`
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::io::Write;
+    /// use queen_io::sys::memfd::{MemFd, Seals};
+    ///
+    /// let mut memfd = MemFd::new("payload").unwrap();
+    /// memfd.write_all(b"hello").unwrap();
+    /// memfd.add_seals(Seals::immutable() | Seals::seal()).unwrap();
+    ///
+    /// let map = memfd.map().unwrap();
+    /// assert_eq!(&map[..], b"hello");
+    /// ```
+    pub fn new(name: &str) -> io::Result<MemFd> {
+        MemFd::with_options(name, MFD_CLOEXEC | MFD_ALLOW_SEALING)
+    }
+
+    pub fn with_options(name: &str, flags: u32) -> io::Result<MemFd> {
+        let name = CString::new(name)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "name contains a nul byte"))?;
+
+        let memfd = syscall!(memfd_create(name.as_ptr(), flags))?;
+
+        Ok(MemFd {
+            inner: unsafe { FileDesc::new(memfd) },
+        })
+    }
+
+    pub fn try_clone(&self) -> io::Result<MemFd> {
+        self.inner.try_clone().map(|fd| MemFd { inner: fd })
+    }
+
+    /// The current size of the file.
+    pub fn len(&self) -> io::Result<u64> {
+        let mut stat: libc::stat = unsafe { mem::zeroed() };
+        syscall!(fstat(self.as_raw_fd(), &mut stat))?;
+        Ok(stat.st_size as u64)
+    }
+
+    pub fn is_empty(&self) -> io::Result<bool> {
+        self.len().map(|len| len == 0)
+    }
+
+    /// ftruncate(2) the file to `len` bytes, fails with `PermissionDenied`
+    /// if a shrink or grow seal forbids it.
+    pub fn set_len(&self, len: u64) -> io::Result<()> {
+        syscall!(ftruncate(self.as_raw_fd(), len as libc::off_t))?;
+        Ok(())
+    }
+
+    /// Add seals to the file, fails with `PermissionDenied` if the file is
+    /// sealed with `Seals::seal()`, and with EBUSY when adding the
+    /// write seal while a writable mapping exists.
+    pub fn add_seals(&self, seals: Seals) -> io::Result<()> {
+        syscall!(fcntl(
+            self.as_raw_fd(),
+            libc::F_ADD_SEALS,
+            seals.as_u32() as libc::c_int
+        ))?;
+        Ok(())
+    }
+
+    pub fn get_seals(&self) -> io::Result<Seals> {
+        let seals = syscall!(fcntl(self.as_raw_fd(), libc::F_GET_SEALS))?;
+        Ok(Seals(seals as u32))
+    }
+
+    /// Map the whole file read only.
+    ///
+    /// The file must be sealed with `Seals::shrink()` and `Seals::write()`, so
+    /// that the mapped contents can not change or be truncated under the
+    /// returned slice, otherwise `PermissionDenied` is returned.
+    pub fn map(&self) -> io::Result<MemMap> {
+        self.require_seals(Seals::shrink() | Seals::write())?;
+
+        let len = self.map_len()?;
+        let ptr = mmap(self.as_raw_fd(), len, libc::PROT_READ)?;
+
+        Ok(MemMap { ptr, len })
+    }
+
+    /// Map the whole file read write.
+    ///
+    /// The file must be sealed with `Seals::shrink()`, so that it can not be
+    /// truncated under the returned slice, otherwise `PermissionDenied` is
+    /// returned. The write seal can not be added while the mapping is alive.
+    ///
+    /// # Safety
+    ///
+    /// The mapping hands out `&mut [u8]` over memory shared with every other
+    /// user of the file. The caller must ensure that, while the mapping is
+    /// alive, the contents are not read or written through anything else:
+    /// another mapping of the file, `write`/`write_at` on this `MemFd` or a
+    /// clone of it, or another process holding the fd.
+    pub unsafe fn map_mut(&self) -> io::Result<MemMapMut> {
+        self.require_seals(Seals::shrink())?;
+
+        let len = self.map_len()?;
+        let ptr = mmap(self.as_raw_fd(), len, libc::PROT_READ | libc::PROT_WRITE)?;
+
+        Ok(MemMapMut { ptr, len })
+    }
+
+    fn require_seals(&self, seals: Seals) -> io::Result<()> {
+        if !self.get_seals()?.contains(seals) {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("memfd must be sealed with {:?}", seals),
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn map_len(&self) -> io::Result<usize> {
+        let len = self.len()?;
+
+        if len > isize::MAX as u64 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "memfd too large to map",
+            ));
+        }
+
+        Ok(len as usize)
+    }
+}
+
+fn mmap(fd: RawFd, len: usize, prot: libc::c_int) -> io::Result<*mut u8> {
+    if len == 0 {
+        return Ok(ptr::NonNull::dangling().as_ptr());
+    }
+
+    let ptr = unsafe { libc::mmap(ptr::null_mut(), len, prot, libc::MAP_SHARED, fd, 0) };
+
+    if ptr == libc::MAP_FAILED {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(ptr as *mut u8)
+}
+
+fn munmap(ptr: *mut u8, len: usize) {
+    if len != 0 {
+        unsafe {
+            let _ = libc::munmap(ptr as *mut libc::c_void, len);
+        }
+    }
+}
+
+/// A read only shared mapping of a sealed `MemFd`, unmapped on drop.
+#[derive(Debug)]
+pub struct MemMap {
+    ptr: *mut u8,
+    len: usize,
+}
+
+unsafe impl Send for MemMap {}
+unsafe impl Sync for MemMap {}
+
+impl ops::Deref for MemMap {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.ptr, self.len) }
+    }
+}
+
+impl Drop for MemMap {
+    fn drop(&mut self) {
+        munmap(self.ptr, self.len)
+    }
+}
+
+/// A read write shared mapping of a `MemFd`, unmapped on drop.
+#[derive(Debug)]
+pub struct MemMapMut {
+    ptr: *mut u8,
+    len: usize,
+}
+
+unsafe impl Send for MemMapMut {}
+unsafe impl Sync for MemMapMut {}
+
+impl ops::Deref for MemMapMut {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.ptr, self.len) }
+    }
+}
+
+impl ops::DerefMut for MemMapMut {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        unsafe { slice::from_raw_parts_mut(self.ptr, self.len) }
+    }
+}
+
+impl Drop for MemMapMut {
+    fn drop(&mut self) {
+        munmap(self.ptr, self.len)
+    }
+}
+
+impl Read for MemFd {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl Write for MemFd {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl Seek for MemFd {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+impl FileExt for MemFd {
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        self.inner.read_at(buf, offset)
+    }
+
+    fn write_at(&self, buf: &[u8], offset: u64) -> io::Result<usize> {
+        self.inner.write_at(buf, offset)
+    }
+}
+
+impl FromRawFd for MemFd {
+    unsafe fn from_raw_fd(fd: RawFd) -> Self {
+        MemFd {
+            inner: FileDesc::new(fd),
+        }
+    }
+}
+
+impl IntoRawFd for MemFd {
+    fn into_raw_fd(self) -> RawFd {
+        self.inner.into_raw_fd()
+    }
+}
+
+impl AsRawFd for MemFd {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{ErrorKind, Write};
+
+    use super::{MemFd, Seals};
+
+    #[test]
+    fn map_mut_then_seal() {
+        let memfd = MemFd::new("test").unwrap();
+        memfd.set_len(4096).unwrap();
+
+        assert!(unsafe { memfd.map_mut() }.is_err());
+
+        memfd.add_seals(Seals::shrink() | Seals::grow()).unwrap();
+
+        let mut map = unsafe { memfd.map_mut() }.unwrap();
+        map[..5].copy_from_slice(b"hello");
+
+        let err = memfd.add_seals(Seals::write()).unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::EBUSY));
+
+        drop(map);
+
+        memfd.add_seals(Seals::write() | Seals::seal()).unwrap();
+        assert_eq!(
+            memfd.get_seals().unwrap(),
+            Seals::immutable() | Seals::seal()
+        );
+
+        let map = memfd.map().unwrap();
+        assert_eq!(map.len(), 4096);
+        assert_eq!(&map[..5], b"hello");
+    }
+
+    #[test]
+    fn sealed_is_immutable() {
+        let mut memfd = MemFd::new("test").unwrap();
+        memfd.write_all(b"hello").unwrap();
+        memfd.add_seals(Seals::immutable()).unwrap();
+
+        assert_eq!(
+            memfd.write(b"world").unwrap_err().kind(),
+            ErrorKind::PermissionDenied
+        );
+        assert_eq!(
+            memfd.set_len(0).unwrap_err().kind(),
+            ErrorKind::PermissionDenied
+        );
+        assert_eq!(
+            memfd.set_len(4096).unwrap_err().kind(),
+            ErrorKind::PermissionDenied
+        );
+    }
+}
@@ -19,6 +19,10 @@ pub mod epoll;
 pub mod timerfd;
 pub mod eventfd;
 pub mod socket;
+pub mod cmsg;
+pub mod pipe;
+pub mod memfd;
+pub mod rlimit;
 
 pub trait IsMinusOne {
     fn is_minus_one(&self) -> bool;
@@ -0,0 +1,360 @@
+use std::io::{self, ErrorKind, Read, Write};
+use std::os::unix::io::{AsRawFd, FromRawFd, IntoRawFd, RawFd};
+use std::ptr;
+
+use libc;
+
+use crate::epoll::{Epoll, EpollOpt, Ready, Source, Token};
+
+use super::fd::FileDesc;
+
+pub const SPLICE_F_MOVE: u32 = libc::SPLICE_F_MOVE;
+pub const SPLICE_F_NONBLOCK: u32 = libc::SPLICE_F_NONBLOCK;
+pub const SPLICE_F_MORE: u32 = libc::SPLICE_F_MORE;
+pub const SPLICE_F_GIFT: u32 = libc::SPLICE_F_GIFT;
+
+/// Create a pipe with flags: O_CLOEXEC | O_NONBLOCK
+/// view: `<http://man7.org/linux/man-pages/man2/pipe.2.html>`
+///
+/// # Example
+///
+/// ```
+/// use std::io::{Read, Write};
+/// use queen_io::sys::pipe;
+///
+/// let (mut reader, mut writer) = pipe::pipe().unwrap();
+///
+/// writer.write_all(b"hello").unwrap();
+///
+/// let mut buf = [0u8; 5];
+/// reader.read_exact(&mut buf).unwrap();
+/// assert_eq!(&buf, b"hello");
+/// ```
+pub fn pipe() -> io::Result<(PipeReader, PipeWriter)> {
+    let mut fds = [0 as RawFd; 2];
+    syscall!(pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC | libc::O_NONBLOCK))?;
+
+    unsafe { Ok((PipeReader::from_raw_fd(fds[0]), PipeWriter::from_raw_fd(fds[1]))) }
+}
+
+/// The read end of a pipe.
+#[derive(Debug)]
+pub struct PipeReader {
+    inner: FileDesc,
+}
+
+/// The write end of a pipe.
+#[derive(Debug)]
+pub struct PipeWriter {
+    inner: FileDesc,
+}
+
+impl PipeReader {
+    pub fn try_clone(&self) -> io::Result<PipeReader> {
+        self.inner.try_clone().map(|fd| PipeReader { inner: fd })
+    }
+
+    pub fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        self.inner.set_nonblocking(nonblocking)
+    }
+
+    /// Get the capacity of the pipe in bytes.
+    pub fn capacity(&self) -> io::Result<usize> {
+        get_pipe_size(self.as_raw_fd())
+    }
+
+    /// Set the capacity of the pipe, the kernel rounds it up to a power of
+    /// two pages, the actual capacity is returned.
+    pub fn set_capacity(&self, size: usize) -> io::Result<usize> {
+        set_pipe_size(self.as_raw_fd(), size)
+    }
+}
+
+impl PipeWriter {
+    pub fn try_clone(&self) -> io::Result<PipeWriter> {
+        self.inner.try_clone().map(|fd| PipeWriter { inner: fd })
+    }
+
+    pub fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        self.inner.set_nonblocking(nonblocking)
+    }
+
+    /// Get the capacity of the pipe in bytes.
+    pub fn capacity(&self) -> io::Result<usize> {
+        get_pipe_size(self.as_raw_fd())
+    }
+
+    /// Set the capacity of the pipe, the kernel rounds it up to a power of
+    /// two pages, the actual capacity is returned.
+    pub fn set_capacity(&self, size: usize) -> io::Result<usize> {
+        set_pipe_size(self.as_raw_fd(), size)
+    }
+}
+
+fn get_pipe_size(fd: RawFd) -> io::Result<usize> {
+    syscall!(fcntl(fd, libc::F_GETPIPE_SZ)).map(|size| size as usize)
+}
+
+fn set_pipe_size(fd: RawFd, size: usize) -> io::Result<usize> {
+    let size = size.min(libc::c_int::MAX as usize) as libc::c_int;
+    syscall!(fcntl(fd, libc::F_SETPIPE_SZ, size)).map(|size| size as usize)
+}
+
+/// splice(2) moves up to `len` bytes from `fd_in` to `fd_out`, one of them must
+/// be a pipe. `SPLICE_F_NONBLOCK` is always added to `flags`.
+/// view: `<http://man7.org/linux/man-pages/man2/splice.2.html>`
+///
+/// The call is repeated until `len` bytes are moved, the input reaches end of
+/// file, or either side would block. The number of bytes moved so far is
+/// returned, `WouldBlock` is only returned if nothing could be moved at all.
+/// If an offset is given, it is advanced by the number of bytes moved.
+pub fn splice<I: AsRawFd, O: AsRawFd>(
+    fd_in: &I,
+    mut off_in: Option<&mut i64>,
+    fd_out: &O,
+    mut off_out: Option<&mut i64>,
+    len: usize,
+    flags: u32,
+) -> io::Result<usize> {
+    let flags = flags | SPLICE_F_NONBLOCK;
+
+    transfer(len, |remaining| {
+        let off_in = off_in
+            .as_mut()
+            .map(|off| &mut **off as *mut libc::loff_t)
+            .unwrap_or(ptr::null_mut());
+        let off_out = off_out
+            .as_mut()
+            .map(|off| &mut **off as *mut libc::loff_t)
+            .unwrap_or(ptr::null_mut());
+
+        syscall!(splice(
+            fd_in.as_raw_fd(),
+            off_in,
+            fd_out.as_raw_fd(),
+            off_out,
+            remaining,
+            flags
+        ))
+    })
+}
+
+/// tee(2) duplicates up to `len` bytes from the pipe `fd_in` to the pipe
+/// `fd_out` without consuming them. `SPLICE_F_NONBLOCK` is always added to
+/// `flags`.
+/// view: `<http://man7.org/linux/man-pages/man2/tee.2.html>`
+///
+/// Since the input is not consumed, tee is issued only once and the number of
+/// bytes duplicated is returned, which may be less than `len`.
+pub fn tee(fd_in: &PipeReader, fd_out: &PipeWriter, len: usize, flags: u32) -> io::Result<usize> {
+    let flags = flags | SPLICE_F_NONBLOCK;
+
+    loop {
+        match syscall!(tee(fd_in.as_raw_fd(), fd_out.as_raw_fd(), len, flags)) {
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => {}
+            other => return other.map(|n| n as usize),
+        }
+    }
+}
+
+/// vmsplice(2) maps the user pages of `buf` into the pipe `fd_out`.
+/// `SPLICE_F_NONBLOCK` is always added to `flags`.
+/// view: `<http://man7.org/linux/man-pages/man2/vmsplice.2.html>`
+///
+/// As with `splice`, the number of bytes moved before the pipe is full is
+/// returned. The pages are referenced by the pipe until they are read, `buf`
+/// should not be modified before the reader has consumed it.
+pub fn vmsplice(fd_out: &PipeWriter, buf: &[u8], flags: u32) -> io::Result<usize> {
+    let flags = flags | SPLICE_F_NONBLOCK;
+
+    let mut pos = 0;
+
+    transfer(buf.len(), |_| {
+        let iov = libc::iovec {
+            iov_base: buf[pos..].as_ptr() as *mut libc::c_void,
+            iov_len: buf.len() - pos,
+        };
+
+        let ret = syscall!(vmsplice(fd_out.as_raw_fd(), &iov, 1, flags));
+
+        if let Ok(n) = ret {
+            pos += n as usize;
+        }
+
+        ret
+    })
+}
+
+pub(crate) fn transfer<F>(len: usize, mut f: F) -> io::Result<usize>
+where
+    F: FnMut(usize) -> io::Result<isize>,
+{
+    let mut moved = 0;
+
+    while moved < len {
+        match f(len - moved) {
+            Ok(0) => break,
+            Ok(n) => moved += n as usize,
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(ref e) if e.kind() == ErrorKind::WouldBlock && moved > 0 => break,
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(moved)
+}
+
+impl Read for PipeReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl Read for &PipeReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        (&self.inner).read(buf)
+    }
+}
+
+impl Write for PipeWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl Write for &PipeWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        (&self.inner).write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        (&self.inner).flush()
+    }
+}
+
+impl FromRawFd for PipeReader {
+    unsafe fn from_raw_fd(fd: RawFd) -> Self {
+        PipeReader {
+            inner: FileDesc::new(fd),
+        }
+    }
+}
+
+impl IntoRawFd for PipeReader {
+    fn into_raw_fd(self) -> RawFd {
+        self.inner.into_raw_fd()
+    }
+}
+
+impl AsRawFd for PipeReader {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+impl Source for PipeReader {
+    fn add(&self, epoll: &Epoll, token: Token, interest: Ready, opts: EpollOpt) -> io::Result<()> {
+        epoll.add(&self.as_raw_fd(), token, interest, opts)
+    }
+
+    fn modify(&self, epoll: &Epoll, token: Token, interest: Ready, opts: EpollOpt) -> io::Result<()> {
+        epoll.modify(&self.as_raw_fd(), token, interest, opts)
+    }
+
+    fn delete(&self, epoll: &Epoll) -> io::Result<()> {
+        epoll.delete(&self.as_raw_fd())
+    }
+}
+
+impl FromRawFd for PipeWriter {
+    unsafe fn from_raw_fd(fd: RawFd) -> Self {
+        PipeWriter {
+            inner: FileDesc::new(fd),
+        }
+    }
+}
+
+impl IntoRawFd for PipeWriter {
+    fn into_raw_fd(self) -> RawFd {
+        self.inner.into_raw_fd()
+    }
+}
+
+impl AsRawFd for PipeWriter {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+impl Source for PipeWriter {
+    fn add(&self, epoll: &Epoll, token: Token, interest: Ready, opts: EpollOpt) -> io::Result<()> {
+        epoll.add(&self.as_raw_fd(), token, interest, opts)
+    }
+
+    fn modify(&self, epoll: &Epoll, token: Token, interest: Ready, opts: EpollOpt) -> io::Result<()> {
+        epoll.modify(&self.as_raw_fd(), token, interest, opts)
+    }
+
+    fn delete(&self, epoll: &Epoll) -> io::Result<()> {
+        epoll.delete(&self.as_raw_fd())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{ErrorKind, Read, Write};
+
+    use super::{pipe, splice, tee, vmsplice};
+
+    #[test]
+    fn read_would_block() {
+        let (mut reader, _writer) = pipe().unwrap();
+
+        let mut buf = [0u8; 8];
+        let err = reader.read(&mut buf).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::WouldBlock);
+    }
+
+    #[test]
+    fn splice_and_tee() {
+        let (reader1, mut writer1) = pipe().unwrap();
+        let (mut reader2, writer2) = pipe().unwrap();
+        let (mut reader3, writer3) = pipe().unwrap();
+
+        writer1.write_all(b"hello world").unwrap();
+
+        assert_eq!(tee(&reader1, &writer3, 64, 0).unwrap(), 11);
+        assert_eq!(splice(&reader1, None, &writer2, None, 64, 0).unwrap(), 11);
+
+        let err = splice(&reader1, None, &writer2, None, 64, 0).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::WouldBlock);
+
+        let mut buf = [0u8; 11];
+        reader2.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"hello world");
+
+        reader3.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"hello world");
+    }
+
+    #[test]
+    fn vmsplice_partial() {
+        let (mut reader, writer) = pipe().unwrap();
+        let capacity = writer.capacity().unwrap();
+
+        let data = vec![7u8; capacity * 2];
+        let n = vmsplice(&writer, &data, 0).unwrap();
+        assert!(n > 0 && n <= capacity);
+
+        let err = vmsplice(&writer, &data[n..], 0).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::WouldBlock);
+
+        let mut buf = vec![0u8; n];
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, &data[..n]);
+    }
+}
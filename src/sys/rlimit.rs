@@ -0,0 +1,31 @@
+use std::io;
+use std::mem;
+
+/// Raise the soft limit of RLIMIT_NOFILE to the hard limit and return the
+/// new limit. Call it once at startup, before the process opens many files.
+/// view: `<http://man7.org/linux/man-pages/man2/getrlimit.2.html>`
+pub fn raise_nofile_limit() -> io::Result<u64> {
+    let mut limit: libc::rlimit = unsafe { mem::zeroed() };
+
+    syscall!(getrlimit(libc::RLIMIT_NOFILE, &mut limit))?;
+
+    if limit.rlim_cur < limit.rlim_max {
+        limit.rlim_cur = limit.rlim_max;
+
+        syscall!(setrlimit(libc::RLIMIT_NOFILE, &limit))?;
+    }
+
+    Ok(limit.rlim_cur as u64)
+}
+
+#[cfg(test)]
+mod test {
+    use super::raise_nofile_limit;
+
+    #[test]
+    fn raise() {
+        let limit = raise_nofile_limit().unwrap();
+
+        assert_eq!(raise_nofile_limit().unwrap(), limit);
+    }
+}
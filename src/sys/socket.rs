@@ -1,9 +1,17 @@
+use std::ffi::OsStr;
 use std::io;
 use std::mem;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::ffi::OsStrExt;
 use std::os::unix::io::RawFd;
+use std::os::unix::net;
+use std::time::{Duration, SystemTime};
 
 use libc::{self, c_int, c_void};
 
+use super::cmsg::{self, Cmsg, CmsgBuf};
+
 pub fn setsockopt<T>(fd: RawFd, opt: c_int, val: c_int,
                      payload: T) -> io::Result<()> {
 
@@ -20,6 +28,629 @@ pub fn getsockopt<T: Copy>(fd: RawFd, opt: c_int,
     syscall!(getsockopt(fd, opt, val,
                     &mut slot as *mut _ as *mut _,
                     &mut len))?;
-    assert_eq!(len as usize, mem::size_of::<T>());
+    if len as usize != mem::size_of::<T>() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("getsockopt returned {} bytes, expected {}", len, mem::size_of::<T>())
+        ))
+    }
     Ok(slot)
 }
+
+/// Create a socket with SOCK_NONBLOCK | SOCK_CLOEXEC
+/// view: `<http://man7.org/linux/man-pages/man2/socket.2.html>`
+pub fn socket(domain: c_int, ty: c_int, protocol: c_int) -> io::Result<RawFd> {
+    syscall!(socket(domain, ty | libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC, protocol))
+}
+
+/// The address family of `addr`, AF_INET or AF_INET6.
+pub fn domain(addr: &SocketAddr) -> c_int {
+    match addr {
+        SocketAddr::V4(_) => libc::AF_INET,
+        SocketAddr::V6(_) => libc::AF_INET6,
+    }
+}
+
+/// Convert `addr` to a `sockaddr_storage` and its length, to be passed to
+/// bind(2), connect(2) or sendto(2).
+pub fn socket_addr(addr: &SocketAddr) -> (libc::sockaddr_storage, libc::socklen_t) {
+    let mut storage: libc::sockaddr_storage = unsafe { mem::zeroed() };
+
+    let len = match addr {
+        SocketAddr::V4(addr) => {
+            let sin = libc::sockaddr_in {
+                sin_family: libc::AF_INET as libc::sa_family_t,
+                sin_port: addr.port().to_be(),
+                sin_addr: libc::in_addr {
+                    s_addr: u32::from_ne_bytes(addr.ip().octets()),
+                },
+                sin_zero: [0; 8],
+            };
+
+            unsafe { *(&mut storage as *mut _ as *mut libc::sockaddr_in) = sin };
+            mem::size_of::<libc::sockaddr_in>()
+        }
+        SocketAddr::V6(addr) => {
+            let sin6 = libc::sockaddr_in6 {
+                sin6_family: libc::AF_INET6 as libc::sa_family_t,
+                sin6_port: addr.port().to_be(),
+                sin6_flowinfo: addr.flowinfo(),
+                sin6_addr: libc::in6_addr {
+                    s6_addr: addr.ip().octets(),
+                },
+                sin6_scope_id: addr.scope_id(),
+            };
+
+            unsafe { *(&mut storage as *mut _ as *mut libc::sockaddr_in6) = sin6 };
+            mem::size_of::<libc::sockaddr_in6>()
+        }
+    };
+
+    (storage, len as libc::socklen_t)
+}
+
+/// Convert a `sockaddr_storage` filled by the kernel, e.g. by accept(2) or
+/// recvfrom(2), to a `SocketAddr`.
+pub fn to_socket_addr(
+    storage: &libc::sockaddr_storage,
+    len: libc::socklen_t
+) -> io::Result<SocketAddr> {
+    match storage.ss_family as c_int {
+        libc::AF_INET if len as usize >= mem::size_of::<libc::sockaddr_in>() => {
+            let sin = unsafe { &*(storage as *const _ as *const libc::sockaddr_in) };
+            let ip = Ipv4Addr::from(sin.sin_addr.s_addr.to_ne_bytes());
+
+            Ok(SocketAddr::V4(SocketAddrV4::new(ip, u16::from_be(sin.sin_port))))
+        }
+        libc::AF_INET6 if len as usize >= mem::size_of::<libc::sockaddr_in6>() => {
+            let sin6 = unsafe { &*(storage as *const _ as *const libc::sockaddr_in6) };
+            let ip = Ipv6Addr::from(sin6.sin6_addr.s6_addr);
+
+            Ok(SocketAddr::V6(SocketAddrV6::new(
+                ip,
+                u16::from_be(sin6.sin6_port),
+                sin6.sin6_flowinfo,
+                sin6.sin6_scope_id
+            )))
+        }
+        _ => Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid socket address")),
+    }
+}
+
+/// Convert a `sockaddr_un` filled by the kernel to a unix `SocketAddr`,
+/// unnamed, pathname or abstract.
+pub fn to_unix_addr(
+    storage: &libc::sockaddr_un,
+    len: libc::socklen_t,
+) -> io::Result<net::SocketAddr> {
+    let offset = mem::size_of::<libc::sa_family_t>();
+    let len = (len as usize).saturating_sub(offset).min(storage.sun_path.len());
+
+    let path = unsafe { &*(&storage.sun_path[..len] as *const [libc::c_char] as *const [u8]) };
+
+    match path.first() {
+        None => net::SocketAddr::from_pathname(""),
+        Some(0) => net::SocketAddr::from_abstract_name(&path[1..]),
+        Some(_) => {
+            let path = path.split(|&b| b == 0).next().unwrap_or(&[]);
+            net::SocketAddr::from_pathname(OsStr::from_bytes(path))
+        }
+    }
+}
+
+/// Convert a unix `SocketAddr` to a `sockaddr_un` and its length, to be
+/// passed to bind(2), connect(2) or sendto(2).
+pub fn unix_socket_addr(addr: &net::SocketAddr) -> (libc::sockaddr_un, libc::socklen_t) {
+    let mut storage: libc::sockaddr_un = unsafe { mem::zeroed() };
+    storage.sun_family = libc::AF_UNIX as libc::sa_family_t;
+
+    let offset = mem::size_of::<libc::sa_family_t>();
+
+    // std only builds addresses that fit in sun_path, with room for the nul
+    let (start, name) = if let Some(path) = addr.as_pathname() {
+        (0, path.as_os_str().as_bytes())
+    } else if let Some(name) = addr.as_abstract_name() {
+        (1, name)
+    } else {
+        return (storage, offset as libc::socklen_t);
+    };
+
+    for (dst, src) in storage.sun_path[start..].iter_mut().zip(name) {
+        *dst = *src as libc::c_char;
+    }
+
+    // the trailing nul of a path, or the leading nul of an abstract name
+    let len = offset + name.len() + 1;
+
+    (storage, len as libc::socklen_t)
+}
+
+/// accept4(2) a connection with SOCK_NONBLOCK | SOCK_CLOEXEC, retrying on
+/// EINTR. The peer address is written to `storage`, its length is returned.
+/// view: `<http://man7.org/linux/man-pages/man2/accept.2.html>`
+pub fn accept4<T>(fd: RawFd, storage: &mut T) -> io::Result<(RawFd, libc::socklen_t)> {
+    let flags = libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC;
+
+    loop {
+        let mut len = mem::size_of::<T>() as libc::socklen_t;
+
+        match syscall!(accept4(fd, storage as *mut _ as *mut libc::sockaddr, &mut len, flags)) {
+            Ok(conn) => return Ok((conn, len)),
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Call `accept` until `max` connections are accepted or it fails, skipping
+/// connections aborted before they were accepted. The error is only returned
+/// if nothing was accepted.
+pub fn accept_batch<T, F>(max: usize, mut accept: F) -> io::Result<Vec<T>>
+where
+    F: FnMut() -> io::Result<T>,
+{
+    let mut accepted = Vec::new();
+
+    while accepted.len() < max {
+        match accept() {
+            Ok(conn) => accepted.push(conn),
+            Err(ref e) if e.kind() == io::ErrorKind::ConnectionAborted => {}
+            Err(e) => {
+                if accepted.is_empty() {
+                    return Err(e);
+                }
+
+                break;
+            }
+        }
+    }
+
+    Ok(accepted)
+}
+
+/// recvmsg(2) into `buf`, retrying on EINTR. The peer address is written to
+/// `name`, the ancillary data to `control`. The bytes received, the length of
+/// the address and the flags of the message are returned.
+/// view: `<http://man7.org/linux/man-pages/man2/recvmsg.2.html>`
+pub fn recvmsg<T>(
+    fd: RawFd,
+    buf: &mut [u8],
+    name: Option<&mut T>,
+    control: &mut CmsgBuf,
+    flags: c_int,
+) -> io::Result<(usize, libc::socklen_t, c_int)> {
+    let mut iovec = libc::iovec {
+        iov_base: buf.as_mut_ptr() as *mut c_void,
+        iov_len: buf.len(),
+    };
+
+    let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+    if let Some(name) = name {
+        msg.msg_name = name as *mut T as *mut c_void;
+        msg.msg_namelen = mem::size_of::<T>() as libc::socklen_t;
+    }
+    msg.msg_iov = &mut iovec;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr();
+    msg.msg_controllen = control.capacity() as _;
+
+    let len = loop {
+        match syscall!(recvmsg(fd, &mut msg, flags)) {
+            Ok(len) => break len as usize,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    };
+
+    control.set_len(msg.msg_controllen as usize);
+
+    Ok((len, msg.msg_namelen, msg.msg_flags))
+}
+
+/// sendmsg(2) `buf` with the ancillary data of `control`, retrying on EINTR.
+/// `name` is the destination address and its length, `None` for connected
+/// sockets.
+/// view: `<http://man7.org/linux/man-pages/man2/sendmsg.2.html>`
+pub fn sendmsg<T>(
+    fd: RawFd,
+    buf: &[u8],
+    name: Option<(&T, libc::socklen_t)>,
+    control: &CmsgBuf,
+    flags: c_int,
+) -> io::Result<usize> {
+    let mut iovec = libc::iovec {
+        iov_base: buf.as_ptr() as *mut c_void,
+        iov_len: buf.len(),
+    };
+
+    let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+    if let Some((name, len)) = name {
+        msg.msg_name = name as *const T as *mut c_void;
+        msg.msg_namelen = len;
+    }
+    msg.msg_iov = &mut iovec;
+    msg.msg_iovlen = 1;
+    if !control.is_empty() {
+        msg.msg_control = control.as_ptr() as *mut c_void;
+        msg.msg_controllen = control.len() as _;
+    }
+
+    loop {
+        match syscall!(sendmsg(fd, &msg, flags | libc::MSG_NOSIGNAL)) {
+            Ok(n) => return Ok(n as usize),
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// A typed socket option, used with `set_sockopt` and `sockopt` of the socket
+/// types in `net`.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use queen_io::net::tcp::TcpListener;
+/// use queen_io::sys::socket::{ReuseAddr, TcpKeepIdle};
+///
+/// let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+/// listener.set_sockopt(ReuseAddr, true).unwrap();
+/// assert!(listener.sockopt(ReuseAddr).unwrap());
+/// ```
+pub trait SockOpt: Copy {
+    type Value;
+
+    fn set(self, fd: RawFd, value: Self::Value) -> io::Result<()>;
+
+    fn get(self, fd: RawFd) -> io::Result<Self::Value>;
+}
+
+macro_rules! sockopt {
+    ($(#[$attr: meta])* $name: ident, $level: expr, $opt: expr, $ty: ty,
+     $to: expr, $from: expr) => {
+        $(#[$attr])*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub struct $name;
+
+        impl SockOpt for $name {
+            type Value = $ty;
+
+            fn set(self, fd: RawFd, value: $ty) -> io::Result<()> {
+                let to: fn($ty) -> c_int = $to;
+                setsockopt(fd, $level, $opt, to(value))
+            }
+
+            fn get(self, fd: RawFd) -> io::Result<$ty> {
+                let from: fn(c_int) -> $ty = $from;
+                getsockopt::<c_int>(fd, $level, $opt).map(from)
+            }
+        }
+    };
+}
+
+fn secs(duration: Duration) -> c_int {
+    duration.as_secs().min(c_int::MAX as u64) as c_int
+}
+
+fn millis(duration: Duration) -> c_int {
+    duration.as_millis().min(c_int::MAX as u128) as c_int
+}
+
+sockopt!(
+    /// SO_REUSEADDR
+    ReuseAddr, libc::SOL_SOCKET, libc::SO_REUSEADDR, bool,
+    |v| v as c_int, |v| v != 0
+);
+
+sockopt!(
+    /// SO_REUSEPORT
+    ReusePort, libc::SOL_SOCKET, libc::SO_REUSEPORT, bool,
+    |v| v as c_int, |v| v != 0
+);
+
+sockopt!(
+    /// SO_RCVBUF, the kernel doubles the value that is set.
+    RcvBuf, libc::SOL_SOCKET, libc::SO_RCVBUF, usize,
+    |v| v.min(c_int::MAX as usize) as c_int, |v| v as usize
+);
+
+sockopt!(
+    /// SO_SNDBUF, the kernel doubles the value that is set.
+    SndBuf, libc::SOL_SOCKET, libc::SO_SNDBUF, usize,
+    |v| v.min(c_int::MAX as usize) as c_int, |v| v as usize
+);
+
+sockopt!(
+    /// SO_KEEPALIVE
+    KeepAlive, libc::SOL_SOCKET, libc::SO_KEEPALIVE, bool,
+    |v| v as c_int, |v| v != 0
+);
+
+sockopt!(
+    /// TCP_KEEPIDLE, the idle time before keepalive probes are sent, in seconds.
+    TcpKeepIdle, libc::IPPROTO_TCP, libc::TCP_KEEPIDLE, Duration,
+    secs, |v| Duration::from_secs(v as u64)
+);
+
+sockopt!(
+    /// TCP_KEEPINTVL, the time between keepalive probes, in seconds.
+    TcpKeepIntvl, libc::IPPROTO_TCP, libc::TCP_KEEPINTVL, Duration,
+    secs, |v| Duration::from_secs(v as u64)
+);
+
+sockopt!(
+    /// TCP_KEEPCNT, the number of unanswered probes before the connection is
+    /// dropped.
+    TcpKeepCnt, libc::IPPROTO_TCP, libc::TCP_KEEPCNT, u32,
+    |v| v.min(c_int::MAX as u32) as c_int, |v| v as u32
+);
+
+sockopt!(
+    /// TCP_USER_TIMEOUT, how long transmitted data may stay unacknowledged, in
+    /// milliseconds. Zero uses the system default.
+    TcpUserTimeout, libc::IPPROTO_TCP, libc::TCP_USER_TIMEOUT, Duration,
+    millis, |v| Duration::from_millis(v as u64)
+);
+
+sockopt!(
+    /// IP_TOS
+    IpTos, libc::IPPROTO_IP, libc::IP_TOS, u8,
+    |v| v as c_int, |v| v as u8
+);
+
+sockopt!(
+    /// IPV6_V6ONLY
+    Ipv6V6Only, libc::IPPROTO_IPV6, libc::IPV6_V6ONLY, bool,
+    |v| v as c_int, |v| v != 0
+);
+
+sockopt!(
+    /// TCP_QUICKACK, the kernel may clear it again, it is not permanent.
+    TcpQuickAck, libc::IPPROTO_TCP, libc::TCP_QUICKACK, bool,
+    |v| v as c_int, |v| v != 0
+);
+
+sockopt!(
+    /// UDP_SEGMENT, the default GSO segment size of the datagrams sent, 0
+    /// disables segmentation offload.
+    UdpSegment, libc::SOL_UDP, libc::UDP_SEGMENT, u16,
+    |v| v as c_int, |v| v as u16
+);
+
+sockopt!(
+    /// UDP_GRO, datagrams of a flow may be coalesced on receive, the segment
+    /// size is reported in a control message.
+    UdpGro, libc::SOL_UDP, libc::UDP_GRO, bool,
+    |v| v as c_int, |v| v != 0
+);
+
+sockopt!(
+    /// IP_PKTINFO, the destination address and the interface of the
+    /// datagrams received are reported in a control message.
+    IpPktInfo, libc::IPPROTO_IP, libc::IP_PKTINFO, bool,
+    |v| v as c_int, |v| v != 0
+);
+
+sockopt!(
+    /// IPV6_RECVPKTINFO, like `IpPktInfo` for IPv6.
+    Ipv6RecvPktInfo, libc::IPPROTO_IPV6, libc::IPV6_RECVPKTINFO, bool,
+    |v| v as c_int, |v| v != 0
+);
+
+sockopt!(
+    /// SO_PASSCRED, the credentials of the sender are reported with every
+    /// message received on a unix socket, see `UnixStream::recv_with_cred`.
+    PassCred, libc::SOL_SOCKET, libc::SO_PASSCRED, bool,
+    |v| v as c_int, |v| v != 0
+);
+
+/// SO_TIMESTAMPNS and SCM_TIMESTAMPNS, not exported by libc, the value of
+/// the architectures using asm-generic/socket.h and of mips.
+#[cfg(all(so_timestamping, not(any(target_arch = "sparc", target_arch = "sparc64"))))]
+pub const SO_TIMESTAMPNS: c_int = 35;
+/// SO_TIMESTAMPING and SCM_TIMESTAMPING, not exported by libc, the value of
+/// the architectures using asm-generic/socket.h and of mips.
+#[cfg(all(so_timestamping, not(any(target_arch = "sparc", target_arch = "sparc64"))))]
+pub const SO_TIMESTAMPING: c_int = 37;
+/// SO_TIMESTAMPNS and SCM_TIMESTAMPNS on sparc.
+#[cfg(any(target_arch = "sparc", target_arch = "sparc64"))]
+pub const SO_TIMESTAMPNS: c_int = 0x21;
+/// SO_TIMESTAMPING and SCM_TIMESTAMPING on sparc.
+#[cfg(any(target_arch = "sparc", target_arch = "sparc64"))]
+pub const SO_TIMESTAMPING: c_int = 0x23;
+
+#[cfg(so_timestamping)]
+sockopt!(
+    /// SO_TIMESTAMPNS, the time each packet was received by the kernel is
+    /// reported in a control message, see `Timestamps`.
+    TimestampNs, libc::SOL_SOCKET, SO_TIMESTAMPNS, bool,
+    |v| v as c_int, |v| v != 0
+);
+
+#[cfg(so_timestamping)]
+sockopt!(
+    /// SO_TIMESTAMPING, a set of `libc::SOF_TIMESTAMPING_*` flags, e.g.
+    /// `SOF_TIMESTAMPING_RX_SOFTWARE | SOF_TIMESTAMPING_SOFTWARE` for software
+    /// receive timestamps, see `Timestamps`.
+    Timestamping, libc::SOL_SOCKET, SO_TIMESTAMPING, u32,
+    |v| v as c_int, |v| v as u32
+);
+
+/// The kernel receive timestamps of a packet, reported in control messages
+/// when `TimestampNs` or `Timestamping` is enabled.
+///
+/// Both options are missing on the architectures whose values of
+/// SO_TIMESTAMPNS and SO_TIMESTAMPING are not known, the timestamps are
+/// always `None` there.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Timestamps {
+    /// The time the kernel received the packet.
+    pub software: Option<SystemTime>,
+    /// The time the NIC received the packet, in the clock of the NIC, with
+    /// `SOF_TIMESTAMPING_RAW_HARDWARE`.
+    pub hardware: Option<Duration>,
+}
+
+impl Timestamps {
+    /// The buffer space to reserve for the timestamp control messages.
+    pub fn cmsg_space() -> usize {
+        cmsg::space(mem::size_of::<[libc::timespec; 3]>())
+            + cmsg::space(mem::size_of::<libc::timespec>())
+    }
+
+    /// Record the timestamp carried by `cmsg`, return whether it was one.
+    pub fn parse(&mut self, cmsg: &Cmsg) -> bool {
+        match (cmsg.level, cmsg.ty) {
+            #[cfg(so_timestamping)]
+            (libc::SOL_SOCKET, SO_TIMESTAMPNS) => {
+                if let Some(ts) = cmsg.read::<libc::timespec>() {
+                    self.software = to_duration(&ts).map(|d| SystemTime::UNIX_EPOCH + d);
+                }
+
+                true
+            }
+            #[cfg(so_timestamping)]
+            (libc::SOL_SOCKET, SO_TIMESTAMPING) => {
+                if let Some(ts) = cmsg.read::<[libc::timespec; 3]>() {
+                    if let Some(d) = to_duration(&ts[0]) {
+                        self.software = Some(SystemTime::UNIX_EPOCH + d);
+                    }
+
+                    self.hardware = to_duration(&ts[2]);
+                }
+
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(so_timestamping)]
+fn to_duration(ts: &libc::timespec) -> Option<Duration> {
+    if ts.tv_sec <= 0 && ts.tv_nsec <= 0 {
+        None
+    } else {
+        Some(Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32))
+    }
+}
+
+/// SO_LINGER, `None` disables lingering, `Some` closes the socket blocking for
+/// at most the given number of seconds while unsent data is flushed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Linger;
+
+impl SockOpt for Linger {
+    type Value = Option<Duration>;
+
+    fn set(self, fd: RawFd, value: Option<Duration>) -> io::Result<()> {
+        let linger = libc::linger {
+            l_onoff: value.is_some() as c_int,
+            l_linger: value.map(secs).unwrap_or(0),
+        };
+
+        setsockopt(fd, libc::SOL_SOCKET, libc::SO_LINGER, linger)
+    }
+
+    fn get(self, fd: RawFd) -> io::Result<Option<Duration>> {
+        let linger: libc::linger = getsockopt(fd, libc::SOL_SOCKET, libc::SO_LINGER)?;
+
+        if linger.l_onoff == 0 {
+            Ok(None)
+        } else {
+            Ok(Some(Duration::from_secs(linger.l_linger as u64)))
+        }
+    }
+}
+
+/// SO_BINDTODEVICE, only packets received on the named interface are
+/// processed by the socket. `None` or an empty name removes the binding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BindToDevice;
+
+impl SockOpt for BindToDevice {
+    type Value = Option<String>;
+
+    fn set(self, fd: RawFd, value: Option<String>) -> io::Result<()> {
+        let name = value.unwrap_or_default();
+
+        if name.len() >= libc::IFNAMSIZ || name.contains('\0') {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid interface name"));
+        }
+
+        syscall!(setsockopt(
+            fd,
+            libc::SOL_SOCKET,
+            libc::SO_BINDTODEVICE,
+            name.as_ptr() as *const c_void,
+            name.len() as libc::socklen_t
+        ))?;
+
+        Ok(())
+    }
+
+    fn get(self, fd: RawFd) -> io::Result<Option<String>> {
+        let mut buf = [0u8; libc::IFNAMSIZ];
+        let mut len = buf.len() as libc::socklen_t;
+
+        syscall!(getsockopt(
+            fd,
+            libc::SOL_SOCKET,
+            libc::SO_BINDTODEVICE,
+            buf.as_mut_ptr() as *mut c_void,
+            &mut len
+        ))?;
+
+        let name = &buf[..len as usize];
+        let name = name.split(|&b| b == 0).next().unwrap_or(&[]);
+
+        if name.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(String::from_utf8_lossy(name).into_owned()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::ErrorKind;
+    use std::net::TcpListener;
+    use std::os::unix::io::AsRawFd;
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn typed_options() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let fd = listener.as_raw_fd();
+
+        ReusePort.set(fd, true).unwrap();
+        assert!(ReusePort.get(fd).unwrap());
+
+        TcpKeepIdle.set(fd, Duration::from_secs(30)).unwrap();
+        assert_eq!(TcpKeepIdle.get(fd).unwrap(), Duration::from_secs(30));
+
+        TcpUserTimeout.set(fd, Duration::from_millis(1500)).unwrap();
+        assert_eq!(TcpUserTimeout.get(fd).unwrap(), Duration::from_millis(1500));
+
+        Linger.set(fd, Some(Duration::from_secs(3))).unwrap();
+        assert_eq!(Linger.get(fd).unwrap(), Some(Duration::from_secs(3)));
+        Linger.set(fd, None).unwrap();
+        assert_eq!(Linger.get(fd).unwrap(), None);
+
+        IpTos.set(fd, 0x10).unwrap();
+        assert_eq!(IpTos.get(fd).unwrap(), 0x10);
+    }
+
+    #[test]
+    fn length_mismatch() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+
+        let err = getsockopt::<u64>(listener.as_raw_fd(), libc::SOL_SOCKET, libc::SO_REUSEADDR)
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+}
@@ -1,9 +1,10 @@
 use std::convert::TryInto;
+use std::error;
 use std::fmt;
 use std::io::{self, Read};
 use std::mem;
 use std::os::unix::io::{AsRawFd, FromRawFd, IntoRawFd, RawFd};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use crate::epoll::{Epoll, EpollOpt, Ready, Source, Token};
 
@@ -72,9 +73,31 @@ pub const TFD_NONBLOCK: i32 = libc::TFD_NONBLOCK;
 const TFD_TIMER_ABSTIME: i32 = libc::TFD_TIMER_ABSTIME;
 const TFD_TIMER_CANCEL_ON_SET: i32 = 0o0000002;
 
+/// The error inside the `io::Error` returned by `read` when the timer was set
+/// with `SetTimeFlags::TimerCancelOnSet` and the real-time clock was changed
+/// discontinuously (ECANCELED), check it with `is_clock_changed`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClockChanged;
+
+impl fmt::Display for ClockChanged {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "timerfd clock changed")
+    }
+}
+
+impl error::Error for ClockChanged {}
+
+/// Whether `err` was returned because the timer's clock was changed.
+pub fn is_clock_changed(err: &io::Error) -> bool {
+    err.get_ref()
+        .map(|inner| inner.is::<ClockChanged>())
+        .unwrap_or(false)
+}
+
 #[derive(Debug)]
 pub struct TimerFd {
     inner: FileDesc,
+    clock: Option<Clock>,
 }
 
 #[derive(Debug, Clone)]
@@ -84,7 +107,7 @@ pub struct TimerSpec {
 }
 
 impl TimerFd {
-    /// Create a timerfd with clickid: CLOCK_REALTIME and flags: TFD_CLOEXEC | TFD_NONBLOCK
+    /// Create a timerfd with clockid: CLOCK_MONOTONIC and flags: TFD_CLOEXEC | TFD_NONBLOCK
     /// view: `<http://man7.org/linux/man-pages/man2/timerfd_create.2.html>`
     ///
     /// # Example
@@ -95,7 +118,7 @@ impl TimerFd {
     /// let timerfd = TimerFd::new();
     /// ```
     pub fn new() -> io::Result<TimerFd> {
-        let clock = Clock::Realtime;
+        let clock = Clock::Monotonic;
         let flags = TFD_CLOEXEC | TFD_NONBLOCK;
         TimerFd::create(clock, flags)
     }
@@ -115,6 +138,7 @@ impl TimerFd {
         let timerfd = syscall!(timerfd_create(clock as i32, flags))?;
         Ok(TimerFd {
             inner: unsafe { FileDesc::new(timerfd) },
+            clock: Some(clock),
         })
     }
 
@@ -192,23 +216,125 @@ impl TimerFd {
         })
     }
 
+    /// Arm the timer to expire once, after `duration`. A zero duration
+    /// expires as soon as possible instead of disarming the timer.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use queen_io::sys::timerfd::TimerFd;
+    ///
+    /// let timerfd = TimerFd::new().unwrap();
+    /// timerfd.set_after(Duration::from_millis(10)).unwrap();
+    /// ```
+    pub fn set_after(&self, duration: Duration) -> io::Result<()> {
+        let timerspec = TimerSpec {
+            interval: Duration::new(0, 0),
+            value: non_zero(duration),
+        };
+
+        self.settime(timerspec, SetTimeFlags::Default).map(|_| ())
+    }
+
+    /// Arm the timer to expire once, at `deadline`. A deadline in the past
+    /// expires as soon as possible.
+    ///
+    /// `Instant` is measured on CLOCK_MONOTONIC, so on a monotonic timer the
+    /// deadline is set as an absolute time (TFD_TIMER_ABSTIME). For other
+    /// clocks, or a timer created with `from_raw_fd`, the remaining time is
+    /// used as a relative timeout.
+    pub fn set_at(&self, deadline: Instant) -> io::Result<()> {
+        if !matches!(self.clock, Some(Clock::Monotonic)) {
+            return self.set_after(deadline.saturating_duration_since(Instant::now()));
+        }
+
+        let now = Instant::now();
+        let mut timespec: libc::timespec = unsafe { mem::zeroed() };
+        syscall!(clock_gettime(libc::CLOCK_MONOTONIC, &mut timespec))?;
+        let monotonic_now = timespec_to_duration(timespec);
+
+        let value = if deadline >= now {
+            monotonic_now.saturating_add(deadline - now)
+        } else {
+            monotonic_now.saturating_sub(now - deadline)
+        };
+
+        let timerspec = TimerSpec {
+            interval: Duration::new(0, 0),
+            value: non_zero(value),
+        };
+
+        self.settime(timerspec, SetTimeFlags::Abstime).map(|_| ())
+    }
+
+    /// Arm the timer to expire every `interval`, starting one `interval` from
+    /// now. `read` returns the number of expirations since the last read.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use queen_io::sys::timerfd::TimerFd;
+    ///
+    /// let timerfd = TimerFd::new().unwrap();
+    /// timerfd.set_interval(Duration::from_secs(1)).unwrap();
+    /// ```
+    pub fn set_interval(&self, interval: Duration) -> io::Result<()> {
+        let interval = non_zero(interval);
+
+        let timerspec = TimerSpec {
+            interval,
+            value: interval,
+        };
+
+        self.settime(timerspec, SetTimeFlags::Default).map(|_| ())
+    }
+
+    /// Stop the timer, expirations that were not read yet are discarded.
+    pub fn disarm(&self) -> io::Result<()> {
+        let timerspec = TimerSpec {
+            interval: Duration::new(0, 0),
+            value: Duration::new(0, 0),
+        };
+
+        self.settime(timerspec, SetTimeFlags::Default).map(|_| ())
+    }
+
     /// read(2) If the timer has already expired one or more times since
     /// its settings were last modified using timerfd_settime(), or since
     /// the last successful read(2), then the buffer given to read(2) returns
     /// an unsigned 8-byte integer (uint64_t) containing the number of
     /// expirations that have occurred. (The returned value is in host byte
     /// order, i.e., the native byte order for integers on the host machine.)
+    ///
+    /// If the timer was set with `SetTimeFlags::TimerCancelOnSet` and the clock
+    /// was changed, an error for which `is_clock_changed` is true is returned.
     pub fn read(&self) -> io::Result<u64> {
         let mut buf = [0u8; 8];
-        (&self.inner).read_exact(&mut buf)?;
-        let temp: u64 = unsafe { mem::transmute(buf) };
-        Ok(temp)
+
+        match (&self.inner).read_exact(&mut buf) {
+            Ok(()) => Ok(u64::from_ne_bytes(buf)),
+            Err(ref e) if e.raw_os_error() == Some(libc::ECANCELED) => {
+                Err(io::Error::other(ClockChanged))
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+fn non_zero(duration: Duration) -> Duration {
+    if duration == Duration::new(0, 0) {
+        Duration::new(0, 1)
+    } else {
+        duration
     }
 }
 
+// saturates, a timer that far away never expires anyway
 fn duration_to_timespec(duration: Duration) -> libc::timespec {
     libc::timespec {
-        tv_sec: duration.as_secs().try_into().unwrap(),
+        tv_sec: duration.as_secs().try_into().unwrap_or(libc::time_t::MAX),
         tv_nsec: duration.subsec_nanos().into(),
     }
 }
@@ -221,6 +347,7 @@ impl FromRawFd for TimerFd {
     unsafe fn from_raw_fd(fd: RawFd) -> Self {
         TimerFd {
             inner: FileDesc::new(fd),
+            clock: None,
         }
     }
 }
@@ -256,3 +383,51 @@ impl Source for TimerFd {
         epoll.delete(&self.as_raw_fd())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::io::ErrorKind;
+    use std::os::unix::io::AsRawFd;
+    use std::time::{Duration, Instant};
+
+    use crate::poll;
+
+    use super::TimerFd;
+
+    #[test]
+    fn set_at_and_disarm() {
+        let timerfd = TimerFd::new().unwrap();
+        assert_eq!(timerfd.read().unwrap_err().kind(), ErrorKind::WouldBlock);
+
+        timerfd.set_at(Instant::now()).unwrap();
+        let timeout = Some(Duration::from_secs(1));
+        let ready = poll::wait(timerfd.as_raw_fd(), poll::Ready::readable(), timeout).unwrap();
+        assert!(ready.is_readable());
+        assert_eq!(timerfd.read().unwrap(), 1);
+
+        let deadline = Instant::now() + Duration::from_secs(10);
+        timerfd.set_at(deadline).unwrap();
+        let remaining = timerfd.gettime().unwrap().value;
+        assert!(remaining > Duration::from_secs(9) && remaining <= Duration::from_secs(10));
+
+        // expirations not read yet are discarded
+        timerfd.set_after(Duration::from_millis(1)).unwrap();
+        poll::wait(timerfd.as_raw_fd(), poll::Ready::readable(), timeout).unwrap();
+        timerfd.disarm().unwrap();
+        assert_eq!(timerfd.gettime().unwrap().value, Duration::new(0, 0));
+        assert_eq!(timerfd.read().unwrap_err().kind(), ErrorKind::WouldBlock);
+    }
+    #[test]
+    fn far_deadlines() {
+        let timerfd = TimerFd::new().unwrap();
+
+        timerfd.set_after(Duration::MAX).unwrap();
+        assert!(timerfd.gettime().unwrap().value > Duration::from_secs(3600 * 24 * 365));
+
+        timerfd.set_interval(Duration::MAX).unwrap();
+        assert!(timerfd.gettime().unwrap().interval > Duration::from_secs(3600 * 24 * 365));
+
+        timerfd.set_at(Instant::now() + Duration::from_secs(u32::MAX as u64)).unwrap();
+        assert_eq!(timerfd.read().unwrap_err().kind(), ErrorKind::WouldBlock);
+    }
+}
@@ -0,0 +1,3 @@
+pub mod timer_heap;
+pub mod ticker;
+pub mod cron;
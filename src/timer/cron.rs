@@ -0,0 +1,464 @@
+use std::io;
+use std::mem;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use slab::Slab;
+
+use crate::epoll::{Epoll, EpollOpt, Ready, Source, Token};
+use crate::sys::timerfd::{
+    self, Clock, SetTimeFlags, TimerFd, TimerSpec, TFD_CLOEXEC, TFD_NONBLOCK,
+};
+
+/// Upper bound of the steps taken by `Schedule::next_after`. The schedules
+/// that never match, e.g. on February 30, are rejected when parsed, the
+/// others match within a few hundred steps.
+const MAX_STEPS: usize = 100_000;
+
+/// The longest length of each month, February 29 included.
+const MONTH_DAYS: [i32; 12] = [31, 29, 31, 30, 31, 30, 31, 31, 30, 31, 30, 31];
+
+/// The time zone in which a `Schedule` is evaluated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeZone {
+    Utc,
+    /// The local time zone of the process, see tzset(3).
+    Local,
+}
+
+/// A calendar schedule with minute precision, in the format of crontab(5):
+/// `minute hour day-of-month month day-of-week`.
+///
+/// Each field accepts `*`, numbers, ranges `a-b`, lists `a,b` and steps
+/// `*/n` or `a-b/n`. Day of week is 0-7, where both 0 and 7 are Sunday. If
+/// both day fields are restricted, a day matching either of them matches.
+/// The macros `@yearly`, `@monthly`, `@weekly`, `@daily` and `@hourly` are
+/// accepted too.
+///
+/// # Example
+///
+/// ```
+/// use queen_io::timer::cron::{Schedule, TimeZone};
+///
+/// let schedule: Schedule = "0 3 * * *".parse().unwrap();
+/// assert_eq!(schedule, Schedule::daily(3, 0).unwrap());
+///
+/// // 2024-01-01 00:00:00 UTC
+/// let now = 1704067200;
+/// let next = schedule.timezone(TimeZone::Utc).next_after(now);
+/// assert_eq!(next, Some(now + 3 * 3600));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Schedule {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    any_day: bool,
+    any_weekday: bool,
+    timezone: TimeZone,
+}
+
+impl Schedule {
+    /// Every day at `hour:minute`, in local time.
+    pub fn daily(hour: u32, minute: u32) -> io::Result<Schedule> {
+        format!("{} {} * * *", minute, hour).parse()
+    }
+
+    /// Every hour at `minute`.
+    pub fn hourly(minute: u32) -> io::Result<Schedule> {
+        format!("{} * * * *", minute).parse()
+    }
+
+    /// Evaluate the schedule in `timezone`, the default is `TimeZone::Local`.
+    pub fn timezone(mut self, timezone: TimeZone) -> Schedule {
+        self.timezone = timezone;
+        self
+    }
+
+    /// The first matching time strictly after `after`, both in seconds since
+    /// the Unix epoch, or `None` if the schedule never matches.
+    pub fn next_after(&self, after: i64) -> Option<i64> {
+        let mut t = after.div_euclid(60) * 60 + 60;
+
+        for _ in 0..MAX_STEPS {
+            let mut tm = self.breakdown(t);
+            tm.tm_sec = 0;
+
+            if !contains(self.months, tm.tm_mon + 1) {
+                tm.tm_mon += 1;
+                tm.tm_mday = 1;
+                tm.tm_hour = 0;
+                tm.tm_min = 0;
+            } else if !self.day_matches(tm.tm_mday, tm.tm_wday) {
+                tm.tm_mday += 1;
+                tm.tm_hour = 0;
+                tm.tm_min = 0;
+            } else if !contains(self.hours, tm.tm_hour) {
+                tm.tm_hour += 1;
+                tm.tm_min = 0;
+            } else if !contains(self.minutes, tm.tm_min) {
+                tm.tm_min += 1;
+            } else {
+                return Some(t);
+            }
+
+            // a daylight saving transition may normalize backwards
+            let next = self.compose(&mut tm);
+            t = if next > t { next } else { t + 60 };
+        }
+
+        None
+    }
+
+    // only a day of month restricted alone can rule out every day, when
+    // none of the days exists in the months
+    fn can_match(&self) -> bool {
+        if self.any_day || !self.any_weekday {
+            return true;
+        }
+
+        (1..=12)
+            .filter(|&month| contains(self.months, month))
+            .any(|month| (1..=MONTH_DAYS[month as usize - 1]).any(|day| contains(self.days, day)))
+    }
+
+    fn day_matches(&self, day: i32, weekday: i32) -> bool {
+        let day = contains(self.days, day);
+        let weekday = contains(self.weekdays, weekday);
+
+        if self.any_day || self.any_weekday {
+            day && weekday
+        } else {
+            day || weekday
+        }
+    }
+
+    fn breakdown(&self, t: i64) -> libc::tm {
+        let mut tm: libc::tm = unsafe { mem::zeroed() };
+        let t = t as libc::time_t;
+
+        unsafe {
+            match self.timezone {
+                TimeZone::Utc => libc::gmtime_r(&t, &mut tm),
+                TimeZone::Local => libc::localtime_r(&t, &mut tm),
+            };
+        }
+
+        tm
+    }
+
+    fn compose(&self, tm: &mut libc::tm) -> i64 {
+        unsafe {
+            match self.timezone {
+                TimeZone::Utc => libc::timegm(tm) as i64,
+                TimeZone::Local => {
+                    tm.tm_isdst = -1;
+                    libc::mktime(tm) as i64
+                }
+            }
+        }
+    }
+}
+
+impl FromStr for Schedule {
+    type Err = io::Error;
+
+    fn from_str(expr: &str) -> io::Result<Schedule> {
+        let expr = match expr.trim() {
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            "@monthly" => "0 0 1 * *",
+            "@weekly" => "0 0 * * 0",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@hourly" => "0 * * * *",
+            expr => expr,
+        };
+
+        let fields: Vec<&str> = expr.split_whitespace().collect();
+
+        if fields.len() != 5 {
+            return Err(invalid("cron expression must have 5 fields"));
+        }
+
+        let mut weekdays = parse_field(fields[4], 0, 7)?;
+        if contains(weekdays, 7) {
+            weekdays = (weekdays & !(1 << 7)) | 1;
+        }
+
+        let schedule = Schedule {
+            minutes: parse_field(fields[0], 0, 59)?,
+            hours: parse_field(fields[1], 0, 23)?,
+            days: parse_field(fields[2], 1, 31)?,
+            months: parse_field(fields[3], 1, 12)?,
+            weekdays,
+            any_day: fields[2].starts_with('*'),
+            any_weekday: fields[4].starts_with('*'),
+            timezone: TimeZone::Local,
+        };
+
+        if !schedule.can_match() {
+            return Err(invalid("cron expression never matches"));
+        }
+
+        Ok(schedule)
+    }
+}
+
+fn parse_field(field: &str, min: u32, max: u32) -> io::Result<u64> {
+    let mut bits = 0;
+
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, parse_number(step)?),
+            None => (part, 1),
+        };
+
+        if step == 0 {
+            return Err(invalid("cron step must be non-zero"));
+        }
+
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some((start, end)) = range.split_once('-') {
+            (parse_number(start)?, parse_number(end)?)
+        } else if part.contains('/') {
+            (parse_number(range)?, max)
+        } else {
+            let value = parse_number(range)?;
+            (value, value)
+        };
+
+        if start < min || end > max || start > end {
+            return Err(invalid(&format!("cron field out of range: {}", part)));
+        }
+
+        for value in (start..=end).step_by(step as usize) {
+            bits |= 1 << value;
+        }
+    }
+
+    Ok(bits)
+}
+
+fn parse_number(s: &str) -> io::Result<u32> {
+    s.parse()
+        .map_err(|_| invalid(&format!("invalid cron number: {}", s)))
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+fn contains(bits: u64, value: i32) -> bool {
+    (0..64).contains(&value) && bits & (1 << value) != 0
+}
+
+/// A handle to a job in a `Cron`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct JobId(pub usize);
+
+#[derive(Debug)]
+struct Job {
+    schedule: Schedule,
+    next: Option<i64>,
+}
+
+/// A scheduler of calendar jobs on a `TimerFd` with `Clock::Realtime`.
+///
+/// The timer is armed with `SetTimeFlags::TimerCancelOnSet`, so when the wall
+/// clock jumps (NTP, settimeofday), it fires and the next deadline of every
+/// job is computed again from the new time. Jobs whose time was skipped by a
+/// jump forward do not fire, and jobs are not repeated after a jump backward.
+///
+/// Register it with `Epoll` for `Ready::readable()` and call `due` when it
+/// fires.
+#[derive(Debug)]
+pub struct Cron {
+    timerfd: TimerFd,
+    jobs: Slab<Job>,
+}
+
+impl Cron {
+    pub fn new() -> io::Result<Cron> {
+        Ok(Cron {
+            timerfd: TimerFd::create(Clock::Realtime, TFD_CLOEXEC | TFD_NONBLOCK)?,
+            jobs: Slab::new(),
+        })
+    }
+
+    pub fn add(&mut self, schedule: Schedule) -> io::Result<JobId> {
+        let next = schedule.next_after(unix_now()?);
+        let key = self.jobs.insert(Job { schedule, next });
+
+        if let Err(err) = self.arm() {
+            self.jobs.remove(key);
+            return Err(err);
+        }
+
+        Ok(JobId(key))
+    }
+
+    pub fn remove(&mut self, id: JobId) -> io::Result<Option<Schedule>> {
+        if !self.jobs.contains(id.0) {
+            return Ok(None);
+        }
+
+        let job = self.jobs.remove(id.0);
+        self.arm()?;
+
+        Ok(Some(job.schedule))
+    }
+
+    /// The next time `id` fires, in seconds since the Unix epoch.
+    pub fn next_run(&self, id: JobId) -> Option<i64> {
+        self.jobs.get(id.0).and_then(|job| job.next)
+    }
+
+    /// Collect the jobs that are due and re-arm the timer. After a clock
+    /// change nothing is due, the deadlines are computed again instead.
+    pub fn due(&mut self) -> io::Result<Vec<JobId>> {
+        let clock_changed = match self.timerfd.read() {
+            Ok(_) => false,
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => false,
+            Err(ref e) if timerfd::is_clock_changed(e) => true,
+            Err(e) => return Err(e),
+        };
+
+        let now = unix_now()?;
+        let mut due = Vec::new();
+
+        for (key, job) in self.jobs.iter_mut() {
+            if clock_changed {
+                job.next = job.schedule.next_after(now);
+                continue;
+            }
+
+            if let Some(next) = job.next {
+                if next <= now {
+                    due.push(JobId(key));
+                    job.next = job.schedule.next_after(now);
+                }
+            }
+        }
+
+        self.arm()?;
+
+        Ok(due)
+    }
+
+    pub fn len(&self) -> usize {
+        self.jobs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.jobs.is_empty()
+    }
+
+    fn arm(&self) -> io::Result<()> {
+        let next = self.jobs.iter().filter_map(|(_, job)| job.next).min();
+
+        match next {
+            Some(next) => {
+                let timerspec = TimerSpec {
+                    interval: Duration::new(0, 0),
+                    value: Duration::from_secs(next.max(1) as u64),
+                };
+
+                self.timerfd
+                    .settime(timerspec, SetTimeFlags::TimerCancelOnSet)
+                    .map(|_| ())
+            }
+            None => self.timerfd.disarm(),
+        }
+    }
+}
+
+fn unix_now() -> io::Result<i64> {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| now.as_secs() as i64)
+        .map_err(io::Error::other)
+}
+
+impl AsRawFd for Cron {
+    fn as_raw_fd(&self) -> RawFd {
+        self.timerfd.as_raw_fd()
+    }
+}
+
+impl Source for Cron {
+    fn add(&self, epoll: &Epoll, token: Token, interest: Ready, opts: EpollOpt) -> io::Result<()> {
+        self.timerfd.add(epoll, token, interest, opts)
+    }
+
+    fn modify(
+        &self,
+        epoll: &Epoll,
+        token: Token,
+        interest: Ready,
+        opts: EpollOpt,
+    ) -> io::Result<()> {
+        self.timerfd.modify(epoll, token, interest, opts)
+    }
+
+    fn delete(&self, epoll: &Epoll) -> io::Result<()> {
+        self.timerfd.delete(epoll)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Cron, Schedule, TimeZone};
+
+    // 2024-01-01 00:00:00 UTC, a Monday
+    const NEW_YEAR: i64 = 1704067200;
+    const DAY: i64 = 86400;
+
+    fn next(expr: &str, after: i64) -> Option<i64> {
+        let schedule: Schedule = expr.parse().unwrap();
+        schedule.timezone(TimeZone::Utc).next_after(after)
+    }
+
+    #[test]
+    fn next_after() {
+        assert_eq!(next("*/15 * * * *", NEW_YEAR), Some(NEW_YEAR + 900));
+        assert_eq!(
+            next("0 3 * * *", NEW_YEAR + 4 * 3600),
+            Some(NEW_YEAR + DAY + 3 * 3600)
+        );
+        assert_eq!(next("@monthly", NEW_YEAR), Some(NEW_YEAR + 31 * DAY));
+        assert_eq!(next("0 12 * * 1-5", NEW_YEAR), Some(NEW_YEAR + 12 * 3600));
+        assert_eq!(next("0 0 * * 6,7", NEW_YEAR), Some(NEW_YEAR + 5 * DAY));
+        assert_eq!(next("0 0 13 * 5", NEW_YEAR), Some(NEW_YEAR + 4 * DAY));
+        assert_eq!(next("0 0 29 2 *", NEW_YEAR), Some(NEW_YEAR + 59 * DAY));
+        assert_eq!(
+            next("0 0 29 2 *", NEW_YEAR + 60 * DAY),
+            Some(NEW_YEAR + 1520 * DAY)
+        );
+    }
+
+    #[test]
+    fn invalid_expression() {
+        assert!("* * * *".parse::<Schedule>().is_err());
+        assert!("60 * * * *".parse::<Schedule>().is_err());
+        assert!("*/0 * * * *".parse::<Schedule>().is_err());
+        assert!("5-1 * * * *".parse::<Schedule>().is_err());
+        assert!("0 0 30 2 *".parse::<Schedule>().is_err());
+        assert!("0 0 31 4,6,9,11 *".parse::<Schedule>().is_err());
+        assert!("0 0 30 2 1".parse::<Schedule>().is_ok());
+    }
+
+    #[test]
+    fn add_and_remove() {
+        let mut cron = Cron::new().unwrap();
+
+        let id = cron.add(Schedule::hourly(0).unwrap()).unwrap();
+        assert!(cron.next_run(id).is_some());
+        assert!(cron.due().unwrap().is_empty());
+
+        assert!(cron.remove(id).unwrap().is_some());
+        assert!(cron.is_empty());
+    }
+}
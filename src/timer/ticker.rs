@@ -0,0 +1,316 @@
+use std::io;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::epoll::{Epoll, EpollOpt, Ready, Source, Token};
+use crate::sys::timerfd::TimerFd;
+
+/// What a `Ticker` does when ticks were missed, because the event loop was
+/// busy for longer than one interval.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MissedTickBehavior {
+    /// Report every missed tick, `Tick::ticks` is the number of intervals that
+    /// elapsed, and the schedule stays on the original grid.
+    Burst,
+    /// Report a single tick for all the missed ones, the next tick is the next
+    /// point of the original grid.
+    Skip,
+    /// Report a single tick, the next tick is one interval from now, so the
+    /// schedule is shifted by the delay.
+    Delay,
+}
+
+/// The result of `Ticker::tick`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tick {
+    /// The number of ticks to run, according to the `MissedTickBehavior`.
+    pub ticks: u64,
+    /// The number of intervals that elapsed since the previous tick, greater
+    /// than one if ticks were missed.
+    pub expirations: u64,
+}
+
+/// A periodic timer on a `TimerFd`, for heartbeats and metrics flushes.
+///
+/// Register it with `Epoll` for `Ready::readable()` and call `tick` when it
+/// fires.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use queen_io::epoll::{Epoll, Events, Token, Ready, EpollOpt};
+/// use queen_io::timer::ticker::{Ticker, MissedTickBehavior};
+///
+/// let epoll = Epoll::new().unwrap();
+/// let mut events = Events::with_capacity(8);
+///
+/// let mut ticker = Ticker::new(Duration::from_millis(10)).unwrap();
+/// ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+/// epoll.add(&ticker, Token(0), Ready::readable(), EpollOpt::level()).unwrap();
+///
+/// epoll.wait(&mut events, None).unwrap();
+/// let tick = ticker.tick().unwrap().unwrap();
+/// assert_eq!(tick.ticks, 1);
+/// ```
+#[derive(Debug)]
+pub struct Ticker {
+    timerfd: TimerFd,
+    interval: Duration,
+    behavior: MissedTickBehavior,
+    jitter: Duration,
+    next: Instant,
+    // the jitter the timer fd is armed with, on top of `next`
+    offset: Duration,
+    rng: u64,
+}
+
+impl Ticker {
+    /// Create a ticker firing every `interval`, starting one `interval` from
+    /// now, with `MissedTickBehavior::Burst` and no jitter.
+    pub fn new(interval: Duration) -> io::Result<Ticker> {
+        if interval == Duration::new(0, 0) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "ticker interval must be non-zero",
+            ));
+        }
+
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|now| now.as_nanos() as u64)
+            .unwrap_or(0);
+
+        let mut ticker = Ticker {
+            timerfd: TimerFd::new()?,
+            interval,
+            behavior: MissedTickBehavior::Burst,
+            jitter: Duration::new(0, 0),
+            next: Instant::now() + interval,
+            offset: Duration::new(0, 0),
+            rng: seed | 1,
+        };
+
+        ticker.arm()?;
+
+        Ok(ticker)
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    pub fn missed_tick_behavior(&self) -> MissedTickBehavior {
+        self.behavior
+    }
+
+    pub fn set_missed_tick_behavior(&mut self, behavior: MissedTickBehavior) {
+        self.behavior = behavior;
+    }
+
+    /// Delay each tick by a random duration in `[0, jitter)`, so that many
+    /// processes with the same interval do not fire at once. The jitter does
+    /// not accumulate, the ticks stay on the grid of the interval.
+    ///
+    /// `InvalidInput` is returned if `jitter` is not shorter than the
+    /// interval.
+    pub fn set_jitter(&mut self, jitter: Duration) -> io::Result<()> {
+        if jitter >= self.interval {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "ticker jitter must be shorter than the interval",
+            ));
+        }
+
+        self.jitter = jitter;
+        self.arm()
+    }
+
+    /// Move the next tick to the next wall-clock multiple of `period`, e.g.
+    /// `Duration::from_secs(1)` ticks on the second boundary. The following
+    /// ticks are one interval apart.
+    pub fn align_to(&mut self, period: Duration) -> io::Result<()> {
+        if period == Duration::new(0, 0) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "ticker period must be non-zero",
+            ));
+        }
+
+        let since_epoch = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(io::Error::other)?;
+
+        let period_nanos = period.as_nanos();
+        let rem = since_epoch.as_nanos() % period_nanos;
+        let wait = (period_nanos - rem) as u64;
+
+        self.next = Instant::now() + Duration::from_nanos(wait);
+        self.arm()
+    }
+
+    /// Restart the schedule, the next tick is one interval from now.
+    pub fn reset(&mut self) -> io::Result<()> {
+        self.next = Instant::now() + self.interval;
+        self.arm()
+    }
+
+    /// Consume the timer fd and compute the ticks that are due.
+    ///
+    /// Returns `None` if the ticker is not due yet, e.g. on a spurious wakeup.
+    pub fn tick(&mut self) -> io::Result<Option<Tick>> {
+        match self.timerfd.read() {
+            Ok(_) => (),
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(None),
+            Err(e) => return Err(e),
+        }
+
+        let now = Instant::now();
+
+        if now < self.next {
+            self.arm()?;
+            return Ok(None);
+        }
+
+        let interval = self.interval.as_nanos();
+        let late = (now - self.next).as_nanos() / interval;
+        let expirations = late as u64 + 1;
+        let elapsed = Duration::from_nanos((interval * (late + 1)) as u64);
+
+        let ticks = match self.behavior {
+            MissedTickBehavior::Burst => {
+                self.next += elapsed;
+                expirations
+            }
+            MissedTickBehavior::Skip => {
+                self.next += elapsed;
+                1
+            }
+            MissedTickBehavior::Delay => {
+                // leave the jitter of this tick out, it must not accumulate
+                self.next = (now - self.offset).max(self.next) + self.interval;
+                1
+            }
+        };
+
+        self.arm()?;
+
+        Ok(Some(Tick { ticks, expirations }))
+    }
+
+    fn arm(&mut self) -> io::Result<()> {
+        self.offset = self.next_jitter();
+        self.timerfd.set_at(self.next + self.offset)
+    }
+
+    fn next_jitter(&mut self) -> Duration {
+        let jitter = self.jitter.as_nanos();
+
+        if jitter == 0 {
+            return Duration::new(0, 0);
+        }
+
+        // xorshift64
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+
+        Duration::from_nanos((self.rng as u128 % jitter) as u64)
+    }
+}
+
+impl AsRawFd for Ticker {
+    fn as_raw_fd(&self) -> RawFd {
+        self.timerfd.as_raw_fd()
+    }
+}
+
+impl Source for Ticker {
+    fn add(&self, epoll: &Epoll, token: Token, interest: Ready, opts: EpollOpt) -> io::Result<()> {
+        self.timerfd.add(epoll, token, interest, opts)
+    }
+
+    fn modify(
+        &self,
+        epoll: &Epoll,
+        token: Token,
+        interest: Ready,
+        opts: EpollOpt,
+    ) -> io::Result<()> {
+        self.timerfd.modify(epoll, token, interest, opts)
+    }
+
+    fn delete(&self, epoll: &Epoll) -> io::Result<()> {
+        self.timerfd.delete(epoll)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::ErrorKind;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use crate::epoll::{Epoll, EpollOpt, Events, Ready, Token};
+
+    use super::{MissedTickBehavior, Ticker};
+
+    #[test]
+    fn missed_ticks() {
+        let interval = Duration::from_millis(10);
+
+        let mut burst = Ticker::new(interval).unwrap();
+        let mut skip = Ticker::new(interval).unwrap();
+        skip.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        let mut delay = Ticker::new(interval).unwrap();
+        delay.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        thread::sleep(Duration::from_millis(35));
+
+        let tick = burst.tick().unwrap().unwrap();
+        assert!(tick.expirations >= 3);
+        assert_eq!(tick.ticks, tick.expirations);
+
+        let tick = skip.tick().unwrap().unwrap();
+        assert!(tick.expirations >= 3);
+        assert_eq!(tick.ticks, 1);
+
+        let now = Instant::now();
+        let tick = delay.tick().unwrap().unwrap();
+        assert!(tick.expirations >= 3);
+        assert_eq!(tick.ticks, 1);
+        assert!(delay.next >= now + interval);
+
+        assert_eq!(burst.tick().unwrap(), None);
+    }
+    #[test]
+    fn jitter() {
+        let interval = Duration::from_millis(20);
+
+        let mut ticker = Ticker::new(interval).unwrap();
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        let err = ticker.set_jitter(interval).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        ticker.set_jitter(Duration::from_millis(19)).unwrap();
+
+        let epoll = Epoll::new().unwrap();
+        let mut events = Events::with_capacity(8);
+        epoll
+            .add(&ticker, Token(0), Ready::readable(), EpollOpt::level())
+            .unwrap();
+
+        for _ in 0..3 {
+            let offset = ticker.offset;
+
+            epoll
+                .wait(&mut events, Some(Duration::from_secs(5)))
+                .unwrap();
+            let tick = ticker.tick().unwrap().unwrap();
+            assert_eq!(tick.ticks, 1);
+
+            // the next tick is one interval after the unjittered deadline
+            assert!(ticker.next + offset <= Instant::now() + interval);
+        }
+    }
+}
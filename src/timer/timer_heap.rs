@@ -0,0 +1,278 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::io;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::time::{Duration, Instant};
+
+use slab::Slab;
+
+use crate::epoll::{Epoll, EpollOpt, Ready, Source, Token};
+use crate::sys::timerfd::TimerFd;
+
+/// Stale heap entries left by `cancel` are only dropped when they reach the
+/// top, the heap is rebuilt once there are more than this many of them and
+/// they outnumber the live timers.
+const COMPACT_THRESHOLD: usize = 1024;
+
+/// A handle to a timer in a `TimerHeap`, used to cancel it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TimerId {
+    key: usize,
+    seq: u64,
+}
+
+#[derive(Debug)]
+struct Entry {
+    seq: u64,
+    token: Token,
+}
+
+/// Many timeouts multiplexed over a single `TimerFd`.
+///
+/// The timer fd is always armed to the nearest deadline. Register the heap
+/// with `Epoll` for `Ready::readable()`, and when it fires call `expired` to
+/// collect the timers that are due.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use queen_io::epoll::{Epoll, Events, Token, Ready, EpollOpt};
+/// use queen_io::timer::timer_heap::TimerHeap;
+///
+/// let epoll = Epoll::new().unwrap();
+/// let mut events = Events::with_capacity(8);
+///
+/// let mut timers = TimerHeap::new().unwrap();
+/// epoll.add(&timers, Token(0), Ready::readable(), EpollOpt::level()).unwrap();
+///
+/// let id = timers.insert(Duration::from_millis(1), Token(7)).unwrap();
+///
+/// epoll.wait(&mut events, None).unwrap();
+/// assert_eq!(timers.expired().unwrap(), vec![(id, Token(7))]);
+/// ```
+#[derive(Debug)]
+pub struct TimerHeap {
+    timerfd: TimerFd,
+    heap: BinaryHeap<Reverse<(Instant, TimerId)>>,
+    entries: Slab<Entry>,
+    next_seq: u64,
+    stale: usize,
+    armed: Option<Instant>,
+}
+
+impl TimerHeap {
+    pub fn new() -> io::Result<TimerHeap> {
+        Ok(TimerHeap {
+            timerfd: TimerFd::new()?,
+            heap: BinaryHeap::new(),
+            entries: Slab::new(),
+            next_seq: 0,
+            stale: 0,
+            armed: None,
+        })
+    }
+
+    /// Add a timer expiring after `delay`.
+    pub fn insert(&mut self, delay: Duration, token: Token) -> io::Result<TimerId> {
+        self.insert_at(Instant::now() + delay, token)
+    }
+
+    /// Add a timer expiring at `deadline`.
+    pub fn insert_at(&mut self, deadline: Instant, token: Token) -> io::Result<TimerId> {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+
+        let key = self.entries.insert(Entry { seq, token });
+        let id = TimerId { key, seq };
+
+        self.heap.push(Reverse((deadline, id)));
+
+        if self.armed.map(|armed| deadline < armed).unwrap_or(true) {
+            if let Err(err) = self.arm(deadline) {
+                let _ = self.cancel(id);
+                return Err(err);
+            }
+        }
+
+        Ok(id)
+    }
+
+    /// Cancel a timer, returns its token if it had not expired yet.
+    ///
+    /// If the cancelled timer was the nearest one, the timer fd is re-armed
+    /// to the next deadline, or disarmed if no timer is left.
+    pub fn cancel(&mut self, id: TimerId) -> io::Result<Option<Token>> {
+        match self.entries.get(id.key) {
+            Some(entry) if entry.seq == id.seq => (),
+            _ => return Ok(None),
+        }
+
+        let entry = self.entries.remove(id.key);
+        self.stale += 1;
+
+        if self.stale > COMPACT_THRESHOLD && self.stale > self.entries.len() {
+            let entries = &self.entries;
+            self.heap.retain(|Reverse((_, id))| is_live(entries, *id));
+            self.stale = 0;
+        }
+
+        self.purge();
+
+        match self.heap.peek() {
+            Some(&Reverse((deadline, _))) if self.armed != Some(deadline) => self.arm(deadline)?,
+            None if self.armed.is_some() => {
+                self.timerfd.disarm()?;
+                self.armed = None;
+            }
+            _ => (),
+        }
+
+        Ok(Some(entry.token))
+    }
+
+    /// Collect the timers that are due, and re-arm the timer fd to the next
+    /// deadline.
+    pub fn expired(&mut self) -> io::Result<Vec<(TimerId, Token)>> {
+        match self.timerfd.read() {
+            Ok(_) => (),
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => (),
+            Err(e) => return Err(e),
+        }
+
+        self.armed = None;
+
+        let now = Instant::now();
+        let mut expired = Vec::new();
+
+        self.purge();
+
+        while let Some(&Reverse((deadline, id))) = self.heap.peek() {
+            if deadline > now {
+                self.arm(deadline)?;
+                break;
+            }
+
+            self.heap.pop();
+            let entry = self.entries.remove(id.key);
+            expired.push((id, entry.token));
+
+            self.purge();
+        }
+
+        Ok(expired)
+    }
+
+    /// The deadline of the nearest timer.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.heap.peek().map(|Reverse((deadline, _))| *deadline)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Drop the cancelled timers from the top of the heap, so that the top is
+    /// always the nearest live timer.
+    fn purge(&mut self) {
+        while let Some(&Reverse((_, id))) = self.heap.peek() {
+            if is_live(&self.entries, id) {
+                break;
+            }
+
+            self.heap.pop();
+            self.stale -= 1;
+        }
+    }
+
+    fn arm(&mut self, deadline: Instant) -> io::Result<()> {
+        self.timerfd.set_at(deadline)?;
+        self.armed = Some(deadline);
+        Ok(())
+    }
+}
+
+fn is_live(entries: &Slab<Entry>, id: TimerId) -> bool {
+    entries
+        .get(id.key)
+        .map(|entry| entry.seq == id.seq)
+        .unwrap_or(false)
+}
+
+impl AsRawFd for TimerHeap {
+    fn as_raw_fd(&self) -> RawFd {
+        self.timerfd.as_raw_fd()
+    }
+}
+
+impl Source for TimerHeap {
+    fn add(&self, epoll: &Epoll, token: Token, interest: Ready, opts: EpollOpt) -> io::Result<()> {
+        self.timerfd.add(epoll, token, interest, opts)
+    }
+
+    fn modify(
+        &self,
+        epoll: &Epoll,
+        token: Token,
+        interest: Ready,
+        opts: EpollOpt,
+    ) -> io::Result<()> {
+        self.timerfd.modify(epoll, token, interest, opts)
+    }
+
+    fn delete(&self, epoll: &Epoll) -> io::Result<()> {
+        self.timerfd.delete(epoll)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::thread;
+    use std::time::Duration;
+
+    use crate::epoll::Token;
+
+    use super::TimerHeap;
+
+    #[test]
+    fn cancel_and_expire_in_order() {
+        let mut timers = TimerHeap::new().unwrap();
+
+        let a = timers.insert(Duration::from_millis(20), Token(1)).unwrap();
+        let b = timers.insert(Duration::from_millis(5), Token(2)).unwrap();
+        let c = timers.insert(Duration::from_millis(10), Token(3)).unwrap();
+
+        assert_eq!(timers.cancel(c).unwrap(), Some(Token(3)));
+        assert_eq!(timers.cancel(c).unwrap(), None);
+        assert_eq!(timers.len(), 2);
+
+        assert!(timers.expired().unwrap().is_empty());
+
+        thread::sleep(Duration::from_millis(30));
+
+        assert_eq!(timers.expired().unwrap(), vec![(b, Token(2)), (a, Token(1))]);
+        assert_eq!(timers.cancel(a).unwrap(), None);
+        assert!(timers.is_empty());
+        assert_eq!(timers.next_deadline(), None);
+    }
+
+    #[test]
+    fn cancel_rearms_to_next_deadline() {
+        let mut timers = TimerHeap::new().unwrap();
+
+        let a = timers.insert(Duration::from_millis(5), Token(1)).unwrap();
+        let b = timers.insert(Duration::from_secs(10), Token(2)).unwrap();
+
+        timers.cancel(a).unwrap();
+        assert_eq!(timers.next_deadline(), timers.armed);
+        assert!(timers.timerfd.gettime().unwrap().value > Duration::from_secs(9));
+
+        timers.cancel(b).unwrap();
+        assert_eq!(timers.next_deadline(), None);
+        assert_eq!(timers.timerfd.gettime().unwrap().value, Duration::new(0, 0));
+    }
+}
@@ -2,7 +2,7 @@ use std::sync::Arc;
 use std::os::unix::io::{RawFd, AsRawFd, FromRawFd};
 use std::io;
 
-use crate::sys::eventfd::EventFd;
+use crate::sys::eventfd::{self, EventFd};
 use crate::epoll::{Ready, Source, Epoll, Token, EpollOpt};
 
 #[derive(Debug, Clone)]
@@ -23,7 +23,7 @@ impl Waker {
         match self.inner.write(1) {
             Ok(_) => Ok(()),
             Err(e) => {
-                if e.kind() == io::ErrorKind::WouldBlock {
+                if eventfd::is_overflow(&e) {
                     Ok(())
                 } else {
                     Err(e)